@@ -3,99 +3,5593 @@
     windows_subsystem = "windows"
 )]
 
-use std::path::PathBuf;
-use tauri::api::dialog::FileDialogBuilder;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use base64::Engine;
+use chardetng::EncodingDetector;
+use encoding_rs::Encoding;
+use filetime::FileTime;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use git2::{Repository, Status, StatusOptions};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use md5::Md5;
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use rayon::prelude::*;
+use regex::{Regex, RegexBuilder};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use similar::{ChangeTag, TextDiff};
+use tauri::api::dialog::blocking::FileDialogBuilder;
 use tauri::{Manager, Runtime};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
 
 // Main entry point
 fn main() {
     // Build the Tauri application
     tauri::Builder::default()
         .setup(|app| {
-            // Setup code here if needed
+            // Failures and panics anywhere in the backend are also reported
+            // to the frontend as "backend-error" events
+            let _ = APP_HANDLE.set(app.handle());
+            let default_hook = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                default_hook(info);
+                report_backend_error("panic", info.to_string());
+            }));
+
+            // A folder passed on the command line (or by the OS through a
+            // file association) opens as the project. The root is set before
+            // the frontend loads, so it can also pick it up through
+            // get_project_root if the event arrives too early.
+            if let Some(folder) = folder_from_args(std::env::args().skip(1)) {
+                match app.state::<ProjectState>().set_root(&folder) {
+                    Ok(()) => {
+                        if let Some(window) = app.get_window("main") {
+                            if let Err(e) = window.emit("open-project", folder.to_string_lossy().to_string()) {
+                                eprintln!("Error emitting open-project event: {}", e);
+                            }
+                        }
+                    }
+                    Err(e) => eprintln!("Error opening project {}: {}", folder.display(), e),
+                }
+            }
             Ok(())
         })
+        .manage(ProjectState::default())
+        .manage(ScanState::default())
+        .manage(SearchState::default())
+        .manage(WatcherState::default())
+        .manage(FileIndexState::default())
+        .manage(FileCacheState::default())
         .invoke_handler(tauri::generate_handler![
             open_folder_dialog,
+            open_file_dialog,
+            save_file_dialog,
+            open_path_dialog,
+            set_project_root,
+            get_project_root,
+            set_workspace_roots,
+            canonicalize_path,
+            resolve_typed_path,
+            add_recent_project,
+            get_recent_projects,
             read_file_content,
+            read_file_with_hash,
+            read_file_lossy,
+            open_file_at,
+            clear_file_cache,
+            read_files,
+            read_file_chunk,
+            read_file_head,
+            stream_file,
+            read_file_with_encoding,
+            read_file_base64,
+            generate_thumbnail,
+            read_file_hex,
+            write_file_content,
+            write_files,
+            format_json,
+            append_to_file,
+            create_file,
+            touch_file,
+            create_file_from_template,
+            create_directory,
+            delete_path,
+            restore_from_trash,
+            empty_directory,
+            rename_path,
+            move_paths,
+            copy_path,
             get_file_tree,
+            get_workspace_tree,
+            refresh_subtree,
+            cancel_tree_scan,
+            read_directory,
+            read_directory_page,
+            immediate_child_count,
+            search_in_files,
+            search_in_files_stream,
+            cancel_search,
+            replace_in_files,
+            find_files_fuzzy,
+            list_files_flat,
+            count_files,
+            tree_counts,
+            language_breakdown,
+            hash_file,
+            checksum_file,
+            file_times,
+            dir_size,
+            disk_usage,
+            stat_path,
+            stat_paths,
+            path_kind,
+            find_case_collisions,
+            file_stats,
+            detect_indentation,
+            detect_line_endings,
+            file_outline,
+            diff_files,
+            zip_directory,
+            unzip_archive,
+            reveal_in_file_manager,
+            open_with_default_app,
+            git_status,
+            git_blame,
+            read_file_at_revision,
+            watch_path,
+            stop_watch,
+            list_watched_paths,
+            watch_file,
+            unwatch_file,
+            tail_file,
+            stop_tail,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
 
-// Command to open folder dialog
+// Command to open folder dialog. The chosen folder is returned and also
+// emitted as "folder-selected".
+#[tauri::command]
+async fn open_folder_dialog<R: Runtime>(
+    window: tauri::Window<R>,
+    project: tauri::State<'_, ProjectState>,
+    set_as_project: Option<bool>,
+) -> Result<Option<String>, AppError> {
+    report_errors("open_folder_dialog", async {
+        // Start where the user last picked a folder, or else in their home dir
+        let app = window.app_handle();
+        let mut recents = load_recents(&app);
+        let start_dir = recents.last_dialog_dir.as_ref()
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_dir())
+            .or_else(dirs::home_dir)
+            .unwrap_or_else(|| PathBuf::from("/"));
+
+        let file_dialog = FileDialogBuilder::new()
+            .set_title("Select Project Folder")
+            .set_directory(start_dir)
+            .pick_folder();
+
+        if let Some(path) = &file_dialog {
+            recents.last_dialog_dir = Some(path.to_string_lossy().to_string());
+            if let Err(e) = save_recents(&app, &recents) {
+                eprintln!("Error saving last dialog directory: {}", e);
+            }
+        }
+
+        // The chosen folder becomes the project unless the caller opts out,
+        // in which case it's still usable like any other picked folder
+        if let Some(path) = &file_dialog {
+            if set_as_project.unwrap_or(true) {
+                project.set_root(path)?;
+            } else {
+                project.allow_picked(path, true)?;
+            }
+        }
+
+        let chosen = file_dialog.map(|path| path.to_string_lossy().to_string());
+        // Also announced as an event, for flows like the native menu where the
+        // frontend didn't make the call itself
+        if let Some(path) = &chosen {
+            if let Err(e) = window.emit("folder-selected", path) {
+                eprintln!("Error emitting folder-selected event: {}", e);
+            }
+        }
+        Ok(chosen)
+    })
+    .await
+}
+
+// Command to open file dialog. Each filter is a name and the extensions
+// (without the dot) it matches. The picked file may be read and written even
+// with no project open or when it's outside the project.
 #[tauri::command]
-async fn open_folder_dialog<R: Runtime>(window: tauri::Window<R>) -> Result<Option<String>, String> {
-    let file_dialog = FileDialogBuilder::new()
-        .set_title("Select Project Folder")
-        .set_directory("/")
-        .pick_folder();
+async fn open_file_dialog(
+    project: tauri::State<'_, ProjectState>,
+    filters: Vec<(String, Vec<String>)>,
+) -> Result<Option<String>, AppError> {
+    report_errors("open_file_dialog", async {
+        let mut builder = FileDialogBuilder::new().set_title("Open File");
+        for (name, extensions) in &filters {
+            let extensions: Vec<&str> = extensions.iter().map(String::as_str).collect();
+            builder = builder.add_filter(name, &extensions);
+        }
 
-    Ok(file_dialog.map(|path| path.to_string_lossy().to_string()))
+        let picked = builder.pick_file();
+        if let Some(path) = &picked {
+            project.allow_picked(path, false)?;
+        }
+        Ok(picked.map(|path| path.to_string_lossy().to_string()))
+    })
+    .await
 }
 
-// Command to read file content
+// Command to open a "Save As" dialog. `default_name` may include a directory,
+// which the dialog starts in. The native dialog doesn't report which filter
+// was chosen, so a path picked without an extension gets the default name's
+// extension, or else the first filter's. Like open_file_dialog, the chosen
+// path may then be written wherever it is.
 #[tauri::command]
-async fn read_file_content(path: String) -> Result<String, String> {
-    std::fs::read_to_string(&path).map_err(|e| e.to_string())
+async fn save_file_dialog(
+    project: tauri::State<'_, ProjectState>,
+    default_name: String,
+    filters: Vec<(String, Vec<String>)>,
+) -> Result<Option<String>, AppError> {
+    report_errors("save_file_dialog", async {
+        let default_path = PathBuf::from(&default_name);
+        let mut builder = FileDialogBuilder::new().set_title("Save As");
+        if let Some(file_name) = default_path.file_name() {
+            builder = builder.set_file_name(&file_name.to_string_lossy());
+        }
+        if let Some(parent) = default_path.parent().filter(|p| p.is_dir()) {
+            builder = builder.set_directory(parent);
+        }
+        for (name, extensions) in &filters {
+            let extensions: Vec<&str> = extensions.iter().map(String::as_str).collect();
+            builder = builder.add_filter(name, &extensions);
+        }
+
+        let chosen = builder.save_file().map(|mut path| {
+            if path.extension().is_none() {
+                let fallback = default_path.extension()
+                    .map(|ext| ext.to_string_lossy().to_string())
+                    .or_else(|| filters.first().and_then(|(_, extensions)| extensions.first().cloned()));
+                if let Some(extension) = fallback {
+                    path.set_extension(extension);
+                }
+            }
+            path
+        });
+        if let Some(path) = &chosen {
+            project.allow_picked(path, false)?;
+        }
+
+        Ok(chosen.map(|path| path.to_string_lossy().to_string()))
+    })
+    .await
 }
 
-// Command to get file tree
+// Command to open whichever dialog `mode` asks for: "file", "folder" or
+// "files" (multi-select). The picked paths always come back as a list, empty
+// if the dialog was cancelled. Picked files, and everything inside a picked
+// folder, may be used by the other commands as with open_file_dialog.
 #[tauri::command]
-async fn get_file_tree(path: String) -> Result<FileEntry, String> {
-    let path_buf = PathBuf::from(&path);
-    build_file_tree(path_buf).map_err(|e| e.to_string())
+async fn open_path_dialog(project: tauri::State<'_, ProjectState>, mode: String) -> Result<Vec<String>, AppError> {
+    report_errors("open_path_dialog", async {
+        let builder = FileDialogBuilder::new();
+        let picked: Vec<PathBuf> = match mode.as_str() {
+            "file" => builder.set_title("Open File").pick_file().into_iter().collect(),
+            "folder" => builder.set_title("Select Folder").pick_folder().into_iter().collect(),
+            "files" => builder.set_title("Open Files").pick_files().unwrap_or_default(),
+            _ => return Err(AppError::InvalidInput(format!("Unknown dialog mode: {}", mode))),
+        };
+        for path in &picked {
+            project.allow_picked(path, mode == "folder")?;
+        }
+
+        Ok(picked.into_iter().map(|path| path.to_string_lossy().to_string()).collect())
+    })
+    .await
 }
 
-// A struct representing a file or directory
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-struct FileEntry {
-    name: String,
+// Command to set the project root that all path commands are confined to
+#[tauri::command]
+async fn set_project_root(project: tauri::State<'_, ProjectState>, path: String) -> Result<(), AppError> {
+    report_errors("set_project_root", async {
+        project.set_root(Path::new(&path))
+    })
+    .await
+}
+
+// Command to set the folders of a multi-root workspace, which may live
+// anywhere. Path commands accept paths inside any of them as well as inside
+// the project root. Replaces whatever roots were set before; every root must
+// be an existing directory.
+#[tauri::command]
+async fn set_workspace_roots(project: tauri::State<'_, ProjectState>, roots: Vec<String>) -> Result<(), AppError> {
+    report_errors("set_workspace_roots", async {
+        project.set_workspace_roots(roots.iter().map(Path::new))
+    })
+    .await
+}
+
+// Command to get the current project root, if a project is open
+#[tauri::command]
+async fn get_project_root(project: tauri::State<'_, ProjectState>) -> Result<Option<String>, AppError> {
+    report_errors("get_project_root", async {
+        Ok(project.root().map(|root| display_path(&root)))
+    })
+    .await
+}
+
+// Command to turn what the user typed into a "go to path" box into the path
+// to pass to other commands, expanding `~` and environment variables
+// (`$HOME/projects`, `%USERPROFILE%\code`). Other commands take names
+// literally, so this is the only place expansion happens.
+#[tauri::command]
+async fn resolve_typed_path(project: tauri::State<'_, ProjectState>, path: String) -> Result<String, AppError> {
+    report_errors("resolve_typed_path", async {
+        Ok(display_path(&project.resolve_typed_path(&path)?))
+    })
+    .await
+}
+
+// Command to resolve a path to its real absolute form, following symlinks
+// and `..`, e.g. to compare it against the project root. The path must exist.
+#[tauri::command]
+async fn canonicalize_path(
+    project: tauri::State<'_, ProjectState>,
     path: String,
-    is_directory: bool,
-    children: Option<Vec<FileEntry>>,
+    path_id: Option<String>,
+) -> Result<String, AppError> {
+    report_errors("canonicalize_path", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        let canonical = std::fs::canonicalize(&path_buf)
+            .map_err(|e| AppError::from_io(e, format!("Failed to resolve {}", path)))?;
+        Ok(display_path(&canonical))
+    })
+    .await
 }
 
-// Helper function to build file tree recursively
-fn build_file_tree(path: PathBuf) -> Result<FileEntry, std::io::Error> {
-    let metadata = std::fs::metadata(&path)?;
-    let file_name = path.file_name()
-        .map(|s| s.to_string_lossy().to_string())
-        .unwrap_or_else(|| String::from(""));
-    
-    let path_str = path.to_string_lossy().to_string();
-    
-    let mut entry = FileEntry {
-        name: file_name,
-        path: path_str,
-        is_directory: metadata.is_dir(),
-        children: None,
-    };
-    
-    if metadata.is_dir() {
-        let mut children = Vec::new();
-        for entry_result in std::fs::read_dir(path)? {
-            let child_entry = entry_result?;
-            let child_path = child_entry.path();
-            
-            // Skip hidden files and special directories
-            let file_name = child_path.file_name()
-                .map(|s| s.to_string_lossy().to_string())
-                .unwrap_or_else(|| String::from(""));
-            
-            if file_name.starts_with(".") || file_name == "node_modules" || file_name == "target" {
+// Command to record a project folder as the most recently opened one
+#[tauri::command]
+async fn add_recent_project<R: Runtime>(app: tauri::AppHandle<R>, path: String) -> Result<(), AppError> {
+    report_errors("add_recent_project", async {
+        let mut recents = load_recents(&app);
+        recents.projects.retain(|p| *p != path);
+        recents.projects.insert(0, path);
+        recents.projects.truncate(MAX_RECENT_PROJECTS);
+        save_recents(&app, &recents)
+    })
+    .await
+}
+
+// Command to list recently opened project folders, most recent first
+#[tauri::command]
+async fn get_recent_projects<R: Runtime>(app: tauri::AppHandle<R>) -> Result<Vec<String>, AppError> {
+    report_errors("get_recent_projects", async {
+        Ok(load_recents(&app).projects)
+    })
+    .await
+}
+
+// Command to read file content. Files larger than `max_bytes` (50 MB by
+// default) are refused; use read_file_chunk to page through those instead.
+// A FileEntry's `path_id` may be passed to open files whose names aren't
+// valid UTF-8; it takes precedence over `path`. Recently read files are
+// served from memory while their size and modification time are unchanged.
+#[tauri::command]
+async fn read_file_content(
+    project: tauri::State<'_, ProjectState>,
+    cache: tauri::State<'_, FileCacheState>,
+    path: String,
+    max_bytes: Option<u64>,
+    path_id: Option<String>,
+) -> Result<String, AppError> {
+    report_errors("read_file_content", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+
+        let max_bytes = max_bytes.unwrap_or(MAX_READ_SIZE);
+        let metadata = std::fs::metadata(&path_buf)?;
+        if metadata.len() <= max_bytes {
+            if let Some(content) = cache.get(&path_buf, &metadata) {
+                return Ok(content);
+            }
+        }
+
+        let content = read_text_file(&path_buf, max_bytes)?;
+        cache.insert(path_buf, &metadata, &content);
+        Ok(content)
+    })
+    .await
+}
+
+// Command to read a text file together with its SHA-256 and modification
+// time (epoch millis), e.g. to revert to the saved version and later pass the
+// hash to write_file_content as `expected_hash`
+#[tauri::command]
+async fn read_file_with_hash(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<HashedContent, AppError> {
+    report_errors("read_file_with_hash", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        let metadata = std::fs::metadata(&path_buf)?;
+        let content = read_text_file(&path_buf, MAX_READ_SIZE)?;
+
+        Ok(HashedContent {
+            sha256: format!("{:x}", Sha256::digest(content.as_bytes())),
+            mtime: metadata.modified().ok().and_then(to_epoch_millis),
+            content,
+        })
+    })
+    .await
+}
+
+// Command to read a file that isn't valid UTF-8 anyway, with each invalid
+// sequence replaced by U+FFFD, for "open anyway" when read_file_content
+// fails. `had_invalid` says whether anything was replaced. The binary check
+// is skipped, but the usual size limit applies.
+#[tauri::command]
+async fn read_file_lossy(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<LossyContent, AppError> {
+    report_errors("read_file_lossy", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        if path_buf.is_dir() {
+            return Err(AppError::IsDirectory(format!("Path is a directory: {}", path)));
+        }
+        ensure_size_within(&path_buf, MAX_READ_SIZE)?;
+
+        let bytes = std::fs::read(&path_buf)
+            .map_err(|e| AppError::from_io(e, format!("Failed to read {}", path)))?;
+        let content = String::from_utf8_lossy(&bytes);
+        Ok(LossyContent {
+            had_invalid: matches!(content, std::borrow::Cow::Owned(_)),
+            content: content.into_owned(),
+        })
+    })
+    .await
+}
+
+// Command to open a file at a (1-based) line, e.g. from a search hit. The
+// content is returned with whether the line exists, so the editor doesn't
+// scroll past the end, and "goto-location" is emitted for other views.
+#[tauri::command]
+async fn open_file_at<R: Runtime>(
+    window: tauri::Window<R>,
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    line: usize,
+    path_id: Option<String>,
+) -> Result<OpenedFile, AppError> {
+    report_errors("open_file_at", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        let content = read_text_file(&path_buf, MAX_READ_SIZE)?;
+
+        // An empty file still has a first line to put the cursor on
+        let line_count = content.lines().count().max(1);
+        if let Err(e) = window.emit("goto-location", GotoLocation { path, line }) {
+            eprintln!("Error emitting goto-location event: {}", e);
+        }
+
+        Ok(OpenedFile { content, valid_line: line >= 1 && line <= line_count })
+    })
+    .await
+}
+
+// Command to drop everything read_file_content has cached
+#[tauri::command]
+async fn clear_file_cache(cache: tauri::State<'_, FileCacheState>) -> Result<(), AppError> {
+    report_errors("clear_file_cache", async {
+        cache.clear();
+        Ok(())
+    })
+    .await
+}
+
+// Command to read several files in one call, e.g. to restore a session's
+// tabs. Files are read concurrently; results come back in the order of
+// `paths`, and one file failing doesn't affect the others.
+#[tauri::command]
+async fn read_files(
+    project: tauri::State<'_, ProjectState>,
+    paths: Vec<String>,
+) -> Result<Vec<FileReadResult>, AppError> {
+    report_errors("read_files", async {
+        let project = project.inner();
+        Ok(paths
+            .into_par_iter()
+            .map(|path| {
+                let result = project.resolve_path(&path)
+                    .and_then(|path_buf| read_text_file(&path_buf, MAX_READ_SIZE));
+                match result {
+                    Ok(content) => FileReadResult { path, content: Some(content), error: None },
+                    Err(e) => FileReadResult { path, content: None, error: Some(e) },
+                }
+            })
+            .collect())
+    })
+    .await
+}
+
+// Command to read a text file in whatever encoding it was saved in. A BOM
+// decides the encoding when present (and is stripped from the content);
+// otherwise the encoding is guessed from the bytes.
+#[tauri::command]
+async fn read_file_with_encoding(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<EncodedContent, AppError> {
+    report_errors("read_file_with_encoding", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        ensure_size_within(&path_buf, MAX_READ_SIZE)?;
+
+        let bytes = std::fs::read(&path_buf)?;
+        let (encoding, bom_len) = match Encoding::for_bom(&bytes) {
+            Some((encoding, bom_len)) => (encoding, bom_len),
+            None => {
+                let mut detector = EncodingDetector::new();
+                detector.feed(&bytes, true);
+                (detector.guess(None, true), 0)
+            }
+        };
+        let (content, _) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+
+        Ok(EncodedContent {
+            content: content.into_owned(),
+            encoding: encoding.name().to_string(),
+            had_bom: bom_len > 0,
+        })
+    })
+    .await
+}
+
+// Command to read up to `length` bytes of a text file starting at `offset`.
+// `length` is capped at MAX_CHUNK_WINDOW. The window is narrowed to whole
+// UTF-8 characters, so the returned `end` is where the next chunk should
+// start; it always holds at least one character before the end of the file,
+// even when `length` is shorter than that character, so a pager can't stall.
+#[tauri::command]
+async fn read_file_chunk(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    offset: u64,
+    length: u64,
+    path_id: Option<String>,
+) -> Result<FileChunk, AppError> {
+    report_errors("read_file_chunk", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+
+        let mut file = std::fs::File::open(&path_buf)?;
+        let total_size = file.metadata()?.len();
+        let start = offset.min(total_size);
+        let length = length.min(MAX_CHUNK_WINDOW);
+
+        // Read a few bytes past the window, enough to finish a character
+        // that the window ends inside of
+        file.seek(SeekFrom::Start(start))?;
+        let mut bytes = Vec::new();
+        (&mut file).take(length + UTF8_LOOKAHEAD).read_to_end(&mut bytes)?;
+        let reaches_end = start + bytes.len() as u64 >= total_size;
+
+        let (skip, keep) = utf8_window(&bytes, length as usize, reaches_end);
+        bytes.truncate(skip + keep);
+        bytes.drain(..skip);
+
+        let content = String::from_utf8(bytes)
+            .map_err(|e| AppError::NotUtf8(format!("File is not valid UTF-8: {}: {}", path, e)))?;
+
+        Ok(FileChunk {
+            start: start + skip as u64,
+            end: start + (skip + keep) as u64,
+            total_size,
+            content,
+        })
+    })
+    .await
+}
+
+// Command to read just the first `lines` lines of a text file, e.g. for a
+// preview of a big CSV or log. Reading stops there (or after MAX_READ_SIZE
+// bytes), so the rest of the file is never touched; shorter files come back
+// whole.
+#[tauri::command]
+async fn read_file_head(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    lines: usize,
+    path_id: Option<String>,
+) -> Result<String, AppError> {
+    report_errors("read_file_head", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+
+        let mut reader = BufReader::new(std::fs::File::open(&path_buf)?).take(MAX_READ_SIZE);
+        if looks_binary(reader.fill_buf()?) {
+            return Err(AppError::IsBinary(format!("File appears to be binary: {}", path)));
+        }
+
+        let mut head = String::new();
+        for _ in 0..lines {
+            if reader.read_line(&mut head)? == 0 {
+                break;
+            }
+        }
+        Ok(head)
+    })
+    .await
+}
+
+// Command to load a whole text file progressively: it's read in
+// STREAM_CHUNK_SIZE pieces, each emitted as a "file-chunk" event (numbered
+// by `seq` from 0), then "file-end" reports how many chunks were sent.
+// Chunks always end on a character boundary.
+#[tauri::command]
+async fn stream_file<R: Runtime>(
+    window: tauri::Window<R>,
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("stream_file", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        if is_binary_file(&path_buf)? {
+            return Err(AppError::IsBinary(format!("File appears to be binary: {}", path)));
+        }
+
+        let mut file = std::fs::File::open(&path_buf)?;
+        let mut buffer = vec![0; STREAM_CHUNK_SIZE];
+        // Bytes of a character cut off at the end of the previous read
+        let mut pending: Vec<u8> = Vec::new();
+        let mut seq = 0;
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            pending.extend_from_slice(&buffer[..read]);
+            let rest = pending.split_off(utf8_complete_len(&pending));
+            let data = String::from_utf8(std::mem::replace(&mut pending, rest))
+                .map_err(|e| AppError::NotUtf8(format!("File is not valid UTF-8: {}: {}", path, e)))?;
+            if data.is_empty() {
                 continue;
             }
-            
-            match build_file_tree(child_path) {
-                Ok(file_entry) => children.push(file_entry),
-                Err(e) => eprintln!("Error processing file: {}", e),
+
+            if let Err(e) = window.emit("file-chunk", StreamChunk { path: path.clone(), seq, data }) {
+                eprintln!("Error emitting file-chunk event: {}", e);
             }
+            seq += 1;
         }
-        
-        entry.children = Some(children);
-    }
-    
-    Ok(entry)
+        if !pending.is_empty() {
+            return Err(AppError::NotUtf8(format!("File ends in the middle of a character: {}", path)));
+        }
+
+        if let Err(e) = window.emit("file-end", StreamEnd { path, total_chunks: seq }) {
+            eprintln!("Error emitting file-end event: {}", e);
+        }
+        Ok(())
+    })
+    .await
+}
+
+// Command to make a thumbnail of an image for the tree or a preview: the
+// image scaled so its longer side is `max_dim` pixels, as base64 PNG.
+// Thumbnails are cached in the app cache directory by path, modification
+// time, file size and `max_dim`, so asking again for an unchanged image is
+// cheap. The cache is kept under THUMBNAIL_CACHE_CAPACITY by dropping the
+// least recently used thumbnails.
+#[tauri::command]
+async fn generate_thumbnail<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    max_dim: u32,
+    path_id: Option<String>,
+) -> Result<String, AppError> {
+    report_errors("generate_thumbnail", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        if max_dim == 0 {
+            return Err(AppError::InvalidInput(String::from("max_dim must be at least 1")));
+        }
+        ensure_size_within(&path_buf, MAX_READ_SIZE)?;
+
+        let metadata = std::fs::metadata(&path_buf)?;
+        let cache_file = app.path_resolver().app_cache_dir().map(|dir| {
+            let key = format!(
+                "{}\0{}\0{}\0{}",
+                path_buf.display(),
+                metadata.modified().ok().and_then(to_epoch_millis).unwrap_or(0),
+                metadata.len(),
+                max_dim
+            );
+            dir.join("thumbnails").join(format!("{:x}.png", Sha256::digest(key.as_bytes())))
+        });
+        if let Some(file) = &cache_file {
+            if let Ok(png) = std::fs::read(file) {
+                // The modification time doubles as the last use, for eviction
+                let _ = filetime::set_file_mtime(file, FileTime::now());
+                return Ok(base64::engine::general_purpose::STANDARD.encode(png));
+            }
+        }
+
+        let image = image::open(&path_buf)?;
+        let mut png = Vec::new();
+        image
+            .resize(max_dim, max_dim, image::imageops::FilterType::Triangle)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)?;
+
+        // A thumbnail that can't be cached is still returned
+        if let Some(file) = &cache_file {
+            let saved = file.parent().map_or(Ok(()), std::fs::create_dir_all).and_then(|_| write_atomic(file, &png));
+            if let Err(e) = saved {
+                eprintln!("Error caching thumbnail for {}: {}", path, e);
+            }
+            if let Some(dir) = file.parent() {
+                if let Err(e) = evict_thumbnails(dir, THUMBNAIL_CACHE_CAPACITY) {
+                    eprintln!("Error trimming the thumbnail cache: {}", e);
+                }
+            }
+        }
+        Ok(base64::engine::general_purpose::STANDARD.encode(png))
+    })
+    .await
+}
+
+// Command to read a file's raw bytes as base64, e.g. to preview images.
+// Files over MAX_READ_SIZE are refused, as with the text reads.
+#[tauri::command]
+async fn read_file_base64(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<BinaryContent, AppError> {
+    report_errors("read_file_base64", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        ensure_size_within(&path_buf, MAX_READ_SIZE)?;
+        let bytes = std::fs::read(&path_buf)?;
+        let mime = infer::get(&bytes)
+            .map(|kind| kind.mime_type().to_string())
+            .unwrap_or_else(|| String::from("application/octet-stream"));
+
+        Ok(BinaryContent {
+            mime,
+            data: base64::engine::general_purpose::STANDARD.encode(&bytes),
+        })
+    })
+    .await
+}
+
+// Command to read a window of a file as hex dump rows of HEX_ROW_WIDTH
+// bytes. `length` is capped at MAX_HEX_WINDOW so the frontend pages through
+// big files instead of loading them whole.
+#[tauri::command]
+async fn read_file_hex(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    offset: u64,
+    length: u64,
+    path_id: Option<String>,
+) -> Result<Vec<HexRow>, AppError> {
+    report_errors("read_file_hex", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+
+        let mut file = std::fs::File::open(&path_buf)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut bytes = Vec::new();
+        file.take(length.min(MAX_HEX_WINDOW)).read_to_end(&mut bytes)?;
+
+        Ok(bytes
+            .chunks(HEX_ROW_WIDTH)
+            .enumerate()
+            .map(|(row, chunk)| HexRow {
+                address: offset + (row * HEX_ROW_WIDTH) as u64,
+                ascii: chunk
+                    .iter()
+                    .map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' })
+                    .collect(),
+                bytes: chunk.to_vec(),
+            })
+            .collect())
+    })
+    .await
+}
+
+// Command to write file content (atomically replaces the file). With
+// `line_ending` every line break is rewritten to LF or CRLF, or with
+// "preserve" to whichever the file on disk mostly uses already. With
+// `trim_trailing_whitespace` spaces and tabs at the end of each line are
+// dropped. `path_id` works as in read_file_content. An existing file is only
+// replaced with `overwrite`; otherwise the write fails with AlreadyExists, so
+// a "new file" save can't clobber one that appeared in the meantime. With
+// `expected_hash` (the sha256 from read_file_with_hash) the file is replaced
+// only if it's still exactly what was read, and Conflict is returned if it
+// changed or disappeared on disk since.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn write_file_content(
+    project: tauri::State<'_, ProjectState>,
+    cache: tauri::State<'_, FileCacheState>,
+    path: String,
+    content: String,
+    line_ending: Option<LineEnding>,
+    trim_trailing_whitespace: Option<bool>,
+    path_id: Option<String>,
+    overwrite: Option<bool>,
+    expected_hash: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("write_file_content", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        check_write_target(&path_buf, overwrite.unwrap_or(false), expected_hash.as_deref())?;
+
+        let content = if trim_trailing_whitespace.unwrap_or(false) { trim_line_ends(&content) } else { content };
+
+        let target = match line_ending {
+            Some(LineEnding::Preserve) => std::fs::read(&path_buf).ok().map(|bytes| dominant_line_ending(&bytes)),
+            other => other,
+        };
+        let content = match target {
+            Some(style) => normalize_line_endings(&content, style),
+            None => content,
+        };
+
+        // Coarse mtimes could make the cached copy look current after a quick save
+        cache.remove(&path_buf);
+        Ok(write_atomic(&path_buf, content.as_bytes())?)
+    })
+    .await
+}
+
+// Command for "Save All": writes several files, each atomically like
+// write_file_content. Every write is attempted even if earlier ones fail, and
+// results come back in the order of `files`.
+#[tauri::command]
+async fn write_files(
+    project: tauri::State<'_, ProjectState>,
+    cache: tauri::State<'_, FileCacheState>,
+    files: Vec<FileWrite>,
+) -> Result<Vec<WriteResult>, AppError> {
+    report_errors("write_files", async {
+        let project = project.inner();
+        let cache = cache.inner();
+        Ok(files
+            .into_par_iter()
+            .map(|file| {
+                let result = project.resolve_path(&file.path).and_then(|path_buf| {
+                    cache.remove(&path_buf);
+                    Ok(write_atomic(&path_buf, file.content.as_bytes())?)
+                });
+                WriteResult { error: result.err(), path: file.path }
+            })
+            .collect())
+    })
+    .await
+}
+
+// Command to reformat a JSON file in place, pretty-printed with 2-space
+// indents or minified. Key order is kept, and numbers are written exactly as
+// they were (`1e2`, `1.10`, integers of any size). Invalid JSON is reported
+// with its line and column and the file is left alone.
+#[tauri::command]
+async fn format_json(
+    project: tauri::State<'_, ProjectState>,
+    cache: tauri::State<'_, FileCacheState>,
+    path: String,
+    pretty: bool,
+    path_id: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("format_json", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        let text = read_text_file(&path_buf, MAX_READ_SIZE)?;
+        let formatted = reformat_json(&text, pretty)?;
+        cache.remove(&path_buf);
+        Ok(write_atomic(&path_buf, formatted.as_bytes())?)
+    })
+    .await
+}
+
+// Command to append text to the end of a file, creating it if needed
+#[tauri::command]
+async fn append_to_file(
+    project: tauri::State<'_, ProjectState>,
+    cache: tauri::State<'_, FileCacheState>,
+    path: String,
+    content: String,
+    path_id: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("append_to_file", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+
+        cache.remove(&path_buf);
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&path_buf)?;
+        file.write_all(content.as_bytes())?;
+        Ok(())
+    })
+    .await
+}
+
+// Command to create a new empty file
+#[tauri::command]
+async fn create_file(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("create_file", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path_buf)
+            .map(|_| ())
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::AlreadyExists => AppError::AlreadyExists(format!("File already exists: {}", path)),
+                _ => AppError::from_io(e, format!("Failed to create file {}", path)),
+            })
+    })
+    .await
+}
+
+// Command to set a file's modified and accessed times to now, creating it
+// empty if it doesn't exist yet (its directory must)
+#[tauri::command]
+async fn touch_file(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("touch_file", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        if let Some(parent) = path_buf.parent().filter(|p| !p.as_os_str().is_empty() && !p.is_dir()) {
+            return Err(AppError::NotFound(format!("Directory does not exist: {}", parent.display())));
+        }
+
+        std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&path_buf)
+            .map_err(|e| AppError::from_io(e, format!("Failed to touch {}", path)))?;
+        let now = FileTime::now();
+        Ok(filetime::set_file_times(&path_buf, now, now)?)
+    })
+    .await
+}
+
+// Command to create a new file pre-filled from a template. `template` is
+// either the name of a built-in template (see builtin_template) or, if it
+// isn't one, the literal content to use.
+#[tauri::command]
+async fn create_file_from_template(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    template: String,
+    path_id: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("create_file_from_template", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+
+        let content = builtin_template(&template).unwrap_or(&template);
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path_buf)
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::AlreadyExists => AppError::AlreadyExists(format!("File already exists: {}", path)),
+                _ => AppError::from_io(e, format!("Failed to create file {}", path)),
+            })?;
+        file.write_all(content.as_bytes())?;
+        Ok(())
+    })
+    .await
+}
+
+// Command to create a directory (and any missing parents)
+#[tauri::command]
+async fn create_directory(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("create_directory", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+
+        std::fs::create_dir_all(&path_buf)
+            .map_err(|e| AppError::from_io(e, format!("Failed to create directory {}", path)))
+    })
+    .await
+}
+
+// Command to delete a file or directory by moving it to the OS trash. The
+// returned `trash_id` can be passed to restore_from_trash to undo this; it's
+// None where the trash can't be read back (macOS).
+#[tauri::command]
+async fn delete_path(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<TrashedItem, AppError> {
+    report_errors("delete_path", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        if std::fs::symlink_metadata(&path_buf).is_err() {
+            return Err(AppError::NotFound(format!("Path does not exist: {}", path)));
+        }
+        // The trash records where things came from with symlinks in the
+        // parent resolved, but not the item itself
+        let original = match (path_buf.parent(), path_buf.file_name()) {
+            (Some(parent), Some(name)) => std::fs::canonicalize(parent)?.join(name),
+            _ => path_buf.clone(),
+        };
+
+        trash::delete(&path_buf).map_err(|e| AppError::Io(format!("Failed to move {} to trash: {}", path, e)))?;
+        Ok(TrashedItem {
+            original_path: original.to_string_lossy().to_string(),
+            trash_id: find_trash_id(&original),
+        })
+    })
+    .await
+}
+
+// Command to put an item deleted with delete_path back where it was, given its
+// `trash_id`. Returns the restored path. Fails with AlreadyExists if something
+// has taken its place since, and with Unsupported where the trash can't be
+// restored from programmatically.
+#[tauri::command]
+async fn restore_from_trash(project: tauri::State<'_, ProjectState>, trash_id: String) -> Result<String, AppError> {
+    report_errors("restore_from_trash", async {
+        restore_trashed(&project, &trash_id)
+    })
+    .await
+}
+
+// Command to remove everything inside a directory but keep the directory,
+// e.g. to clean a build output folder. Children go to the OS trash with
+// `to_trash`, otherwise they're deleted permanently. Every child is
+// attempted and gets a result saying why it couldn't be removed, if it
+// couldn't. The project root itself is refused.
+#[tauri::command]
+async fn empty_directory(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    to_trash: bool,
+    path_id: Option<String>,
+) -> Result<Vec<RemoveResult>, AppError> {
+    report_errors("empty_directory", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        if !path_buf.is_dir() {
+            return Err(AppError::NotFound(format!("Directory does not exist: {}", path)));
+        }
+        let is_root = match project.root() {
+            Some(root) => std::fs::canonicalize(&path_buf)? == std::fs::canonicalize(root)?,
+            None => false,
+        };
+        if is_root {
+            return Err(AppError::PermissionDenied(String::from("Refusing to empty the project root")));
+        }
+
+        let read_dir = std::fs::read_dir(&path_buf)
+            .map_err(|e| AppError::from_io(e, format!("Failed to read directory {}", path)))?;
+        let mut results = Vec::new();
+        for entry_result in read_dir {
+            // An entry that can't be read has no path of its own, so its
+            // failure is reported against the directory
+            let child = match entry_result {
+                Ok(entry) => entry.path(),
+                Err(e) => {
+                    let error = AppError::from_io(e, format!("Failed to read an entry of {}", path));
+                    results.push(RemoveResult { path: path_buf.to_string_lossy().to_string(), error: Some(error) });
+                    continue;
+                }
+            };
+            let error = if to_trash {
+                trash::delete(&child)
+                    .map_err(|e| AppError::Io(format!("Failed to move {} to trash: {}", child.display(), e)))
+            } else {
+                remove_entry(&child).map_err(|e| AppError::from_io(e, format!("Failed to delete {}", child.display())))
+            }
+            .err();
+            results.push(RemoveResult { path: child.to_string_lossy().to_string(), error });
+        }
+        results.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(results)
+    })
+    .await
+}
+
+// Command to rename (or move) a file or directory
+#[tauri::command]
+async fn rename_path(
+    project: tauri::State<'_, ProjectState>,
+    from: String,
+    to: String,
+    from_id: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("rename_path", async {
+        let from_path = command_path(&project, &from, from_id.as_deref())?;
+        let to_path = project.resolve_path(&to)?;
+        move_path(&from_path, &to_path)
+    })
+    .await
+}
+
+// Command to move several files or folders into `dest_dir`, keeping their
+// names. Every item is attempted; the result for each says where it went or
+// why it didn't move (an existing file at the destination is never replaced).
+#[tauri::command]
+async fn move_paths(
+    project: tauri::State<'_, ProjectState>,
+    items: Vec<String>,
+    dest_dir: String,
+) -> Result<Vec<MoveResult>, AppError> {
+    report_errors("move_paths", async {
+        let dest = project.resolve_path(&dest_dir)?;
+        if !dest.is_dir() {
+            return Err(AppError::NotFound(format!("Destination is not a directory: {}", dest_dir)));
+        }
+
+        Ok(items
+            .into_iter()
+            .map(|from| {
+                let from_path = project.resolve_path(&from);
+                let to_path = match from_path.as_ref().ok().and_then(|p| p.file_name()) {
+                    Some(name) => dest.join(name),
+                    None => dest.clone(),
+                };
+                let error = from_path
+                    .and_then(|from_path| match from_path.file_name() {
+                        Some(_) => move_path(&from_path, &to_path),
+                        None => Err(AppError::InvalidInput(format!("Can't move {}", from))),
+                    })
+                    .err();
+                MoveResult { from, to: to_path.to_string_lossy().to_string(), error }
+            })
+            .collect())
+    })
+    .await
+}
+
+// Command to copy a file, or a directory with everything in it
+#[tauri::command]
+async fn copy_path(
+    project: tauri::State<'_, ProjectState>,
+    from: String,
+    to: String,
+    from_id: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("copy_path", async {
+        let from_path = command_path(&project, &from, from_id.as_deref())?;
+        let to_path = project.resolve_path(&to)?;
+
+        if std::fs::symlink_metadata(&to_path).is_ok() {
+            return Err(AppError::AlreadyExists(format!("Target already exists: {}", to)));
+        }
+        if canonicalize_partial(&to_path)?.starts_with(std::fs::canonicalize(&from_path)?) {
+            return Err(AppError::InvalidInput(format!("Cannot copy {} into itself", from)));
+        }
+
+        copy_recursive(&from_path, &to_path)
+            .map_err(|e| AppError::from_io(e, format!("Failed to copy {} to {}", from, to)))
+    })
+    .await
+}
+
+// Command to get file tree. With `max_depth` only that many levels below the
+// root are expanded; deeper directories come back with `children: None`.
+// With `report_progress` a "tree-progress" event is emitted every
+// PROGRESS_INTERVAL entries while the walk runs. `ignore_patterns` are globs
+// hidden on top of the usual rules, and `show_hidden` stops dotfiles being
+// skipped by default. With `prune_empty` directories left with nothing in
+// them after filtering are dropped, as are directories only holding those.
+// With `max_entries` the scan gives up with TooLarge once it has seen more
+// than that many entries, so opening a huge folder doesn't hang. With
+// `compact_folders` chains of directories that only hold one subdirectory
+// are collapsed into a single node named like `com/example/foo`, whose
+// `path` is the deepest directory. With `with_git` each changed or untracked
+// file gets its git_status code, from one status snapshot of the repository
+// taken before the walk.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn get_file_tree<R: Runtime>(
+    window: tauri::Window<R>,
+    project: tauri::State<'_, ProjectState>,
+    scan_state: tauri::State<'_, ScanState>,
+    path: String,
+    max_depth: Option<usize>,
+    report_progress: Option<bool>,
+    ignore_patterns: Option<Vec<String>>,
+    show_hidden: Option<bool>,
+    prune_empty: Option<bool>,
+    max_entries: Option<usize>,
+    compact_folders: Option<bool>,
+    with_git: Option<bool>,
+    path_id: Option<String>,
+) -> Result<FileEntry, AppError> {
+    report_errors("get_file_tree", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        let ignore = IgnoreRules::default()
+            .with_patterns(&path_buf, &ignore_patterns.unwrap_or_default())?
+            .with_hidden(show_hidden.unwrap_or(false));
+
+        let emit_progress = |scanned: usize, current: &Path| {
+            let progress = TreeProgress { scanned, current_path: current.to_string_lossy().to_string() };
+            if let Err(e) = window.emit("tree-progress", progress) {
+                eprintln!("Error emitting tree-progress event: {}", e);
+            }
+        };
+        scan_state.cancelled.store(false, Ordering::Relaxed);
+        let scan = TreeScan {
+            root: path_buf.clone(),
+            on_progress: if report_progress.unwrap_or(false) { Some(&emit_progress) } else { None },
+            cancelled: Some(&scan_state.cancelled),
+            prune_empty: prune_empty.unwrap_or(false),
+            max_entries,
+            git_statuses: if with_git.unwrap_or(false) { Some(git_status_map(&path_buf)?) } else { None },
+            ..TreeScan::default()
+        };
+
+        let mut tree = build_file_tree(path_buf, &ignore, &HashSet::new(), max_depth, &scan)?;
+        if compact_folders.unwrap_or(false) {
+            compact_folder_chains(&mut tree);
+        }
+        Ok(tree)
+    })
+    .await
+}
+
+// Command to rebuild the tree below one directory, e.g. the parent of a path
+// in an "fs-changes" event, so the frontend can splice it in place of that
+// node instead of reloading the whole project. `root` is the directory the
+// tree was built from (the project root by default) and the other options
+// are those it was built with; the branch then comes out exactly as
+// get_file_tree would have built it, with the same ignore rules and
+// `relative_path`s. `max_depth` counts from `path`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn refresh_subtree(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    root: Option<String>,
+    max_depth: Option<usize>,
+    ignore_patterns: Option<Vec<String>>,
+    show_hidden: Option<bool>,
+    prune_empty: Option<bool>,
+    compact_folders: Option<bool>,
+    with_git: Option<bool>,
+    path_id: Option<String>,
+) -> Result<FileEntry, AppError> {
+    report_errors("refresh_subtree", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        let root = project.resolve_root(root)?;
+        if !path_buf.is_dir() {
+            return Err(AppError::InvalidInput(format!("Not a directory: {}", path)));
+        }
+        let ignore = IgnoreRules::default()
+            .with_patterns(&root, &ignore_patterns.unwrap_or_default())?
+            .with_hidden(show_hidden.unwrap_or(false));
+        let (ignore, visited) = descend_to(&root, &path_buf, ignore)?;
+
+        let scan = TreeScan {
+            root: root.clone(),
+            prune_empty: prune_empty.unwrap_or(false),
+            git_statuses: if with_git.unwrap_or(false) { Some(git_status_map(&root)?) } else { None },
+            ..TreeScan::default()
+        };
+        let mut tree = build_file_tree(path_buf, &ignore, &visited, max_depth, &scan)?;
+        if compact_folders.unwrap_or(false) {
+            compact_folder_chains(&mut tree);
+        }
+        Ok(tree)
+    })
+    .await
+}
+
+// Command to build the trees of a multi-root workspace, one top-level entry
+// per root in the order given. Every root must be inside the project or be
+// one of the roots given to set_workspace_roots. A root that can't be listed
+// comes back as a childless entry with `error` set, so the others still
+// load; cancel_tree_scan stops the whole call.
+#[tauri::command]
+async fn get_workspace_tree(
+    project: tauri::State<'_, ProjectState>,
+    scan_state: tauri::State<'_, ScanState>,
+    roots: Vec<String>,
+    max_depth: Option<usize>,
+) -> Result<Vec<FileEntry>, AppError> {
+    report_errors("get_workspace_tree", async {
+        scan_state.cancelled.store(false, Ordering::Relaxed);
+
+        let mut entries = Vec::new();
+        for root in roots {
+            let path_buf = PathBuf::from(&root);
+            let result = project.resolve_path(&root).and_then(|resolved| {
+                let scan = TreeScan {
+                    root: resolved.clone(),
+                    cancelled: Some(&scan_state.cancelled),
+                    ..TreeScan::default()
+                };
+                build_file_tree(resolved, &IgnoreRules::default(), &HashSet::new(), max_depth, &scan)
+            });
+
+            let mut entry = match result {
+                Ok(entry) => entry,
+                Err(e @ AppError::Cancelled(_)) => return Err(e),
+                Err(e) => FileEntry {
+                    path: root.clone(),
+                    path_id: encode_path_id(&path_buf),
+                    is_directory: true,
+                    error: Some(e),
+                    ..FileEntry::default()
+                },
+            };
+            // Label each root by its folder name, or the whole path for `/`
+            if entry.name.is_empty() {
+                entry.name = path_buf.file_name()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or(root);
+            }
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    })
+    .await
+}
+
+// Command to stop a running get_file_tree, which then fails with Cancelled
+#[tauri::command]
+async fn cancel_tree_scan(scan_state: tauri::State<'_, ScanState>) -> Result<(), AppError> {
+    report_errors("cancel_tree_scan", async {
+        scan_state.cancelled.store(true, Ordering::Relaxed);
+        Ok(())
+    })
+    .await
+}
+
+// Command to list the immediate children of a directory (non-recursive).
+// Their `relative_path` is relative to the project root.
+#[tauri::command]
+async fn read_directory(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<Vec<FileEntry>, AppError> {
+    report_errors("read_directory", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        let ignore = IgnoreRules::for_ancestors(&path_buf, project.root().as_deref());
+
+        let mut children = list_children(&path_buf, &project.root().unwrap_or_default(), &ignore)?;
+        fill_has_children(&mut children, &ignore);
+        Ok(children)
+    })
+    .await
+}
+
+// Command to list one page of a directory's children, for folders too big to
+// send in one go. Entries are in the same order as read_directory, which is
+// stable between calls as long as the directory doesn't change; `total` is
+// the number of entries across all pages.
+#[tauri::command]
+async fn read_directory_page(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    offset: usize,
+    limit: usize,
+    path_id: Option<String>,
+) -> Result<DirectoryPage, AppError> {
+    report_errors("read_directory_page", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        let ignore = IgnoreRules::for_ancestors(&path_buf, project.root().as_deref());
+
+        let (mut entries, total) =
+            list_children_page(&path_buf, &project.root().unwrap_or_default(), &ignore, offset, limit)?;
+        fill_has_children(&mut entries, &ignore);
+        Ok(DirectoryPage { entries, total })
+    })
+    .await
+}
+
+// Command to count a directory's non-ignored immediate children, e.g. for a
+// badge on a collapsed folder. Only the directory itself is read; a failure
+// to read it is returned rather than counted as empty.
+#[tauri::command]
+async fn immediate_child_count(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<ChildCounts, AppError> {
+    report_errors("immediate_child_count", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        let ignore = IgnoreRules::for_ancestors(&path_buf, project.root().as_deref());
+
+        let mut counts = ChildCounts { files: 0, dirs: 0 };
+        for entry_result in std::fs::read_dir(&path_buf)? {
+            let child_path = entry_result?.path();
+            let is_dir = child_path.is_dir();
+            if ignore.is_ignored(&child_path, is_dir) {
+                continue;
+            }
+            if is_dir {
+                counts.dirs += 1;
+            } else {
+                counts.files += 1;
+            }
+        }
+        Ok(counts)
+    })
+    .await
+}
+
+// Command to search the contents of every non-ignored text file under a root.
+// With `is_regex` the query is a regular expression; files are matched a line
+// at a time, so patterns can't span line breaks.
+#[tauri::command]
+async fn search_in_files(
+    project: tauri::State<'_, ProjectState>,
+    root: Option<String>,
+    query: String,
+    case_sensitive: bool,
+    is_regex: bool,
+) -> Result<Vec<SearchHit>, AppError> {
+    report_errors("search_in_files", async {
+        let root = project.resolve_root(root)?;
+        let matcher = LineMatcher::new(&query, case_sensitive, is_regex)?;
+
+        let mut hits = Vec::new();
+        walk_tree(&root, project.root().as_deref(), &mut |path, metadata| {
+            if metadata.is_dir() {
+                return true;
+            }
+            if let Err(e) = search_file(path, &matcher, &mut hits) {
+                eprintln!("Error searching file {}: {}", path.display(), e);
+            }
+            hits.len() < MAX_SEARCH_HITS
+        })?;
+
+        hits.truncate(MAX_SEARCH_HITS);
+        Ok(hits)
+    })
+    .await
+}
+
+// Command to search like search_in_files, but report hits as they're found:
+// each file's matches are emitted as "search-hit" events and a final
+// "search-done" carries the total. cancel_search stops it early.
+#[tauri::command]
+async fn search_in_files_stream<R: Runtime>(
+    window: tauri::Window<R>,
+    project: tauri::State<'_, ProjectState>,
+    search_state: tauri::State<'_, SearchState>,
+    root: Option<String>,
+    query: String,
+    case_sensitive: bool,
+    is_regex: bool,
+) -> Result<(), AppError> {
+    report_errors("search_in_files_stream", async {
+        let root = project.resolve_root(root)?;
+        let matcher = LineMatcher::new(&query, case_sensitive, is_regex)?;
+        search_state.cancelled.store(false, Ordering::Relaxed);
+
+        let mut total = 0;
+        let mut cancelled = false;
+        walk_tree(&root, project.root().as_deref(), &mut |path, metadata| {
+            if search_state.cancelled.load(Ordering::Relaxed) {
+                cancelled = true;
+                return false;
+            }
+            if metadata.is_dir() {
+                return true;
+            }
+
+            let mut hits = Vec::new();
+            if let Err(e) = search_file(path, &matcher, &mut hits) {
+                eprintln!("Error searching file {}: {}", path.display(), e);
+            }
+            for hit in hits.into_iter().take(MAX_SEARCH_HITS - total) {
+                total += 1;
+                if let Err(e) = window.emit("search-hit", hit) {
+                    eprintln!("Error emitting search-hit event: {}", e);
+                }
+            }
+            total < MAX_SEARCH_HITS
+        })?;
+
+        if let Err(e) = window.emit("search-done", SearchDone { total, cancelled }) {
+            eprintln!("Error emitting search-done event: {}", e);
+        }
+        Ok(())
+    })
+    .await
+}
+
+// Command to stop a running search_in_files_stream
+#[tauri::command]
+async fn cancel_search(search_state: tauri::State<'_, SearchState>) -> Result<(), AppError> {
+    report_errors("cancel_search", async {
+        search_state.cancelled.store(true, Ordering::Relaxed);
+        Ok(())
+    })
+    .await
+}
+
+// Command to replace every match of `query` in the non-ignored text files
+// under a root. Matching works a line at a time like search_in_files; binary
+// and non-UTF-8 files are skipped. With `dry_run` nothing is written and the
+// results only preview which files would change and by how much.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn replace_in_files(
+    project: tauri::State<'_, ProjectState>,
+    cache: tauri::State<'_, FileCacheState>,
+    root: Option<String>,
+    query: String,
+    replacement: String,
+    case_sensitive: bool,
+    is_regex: bool,
+    dry_run: bool,
+) -> Result<Vec<ReplaceResult>, AppError> {
+    report_errors("replace_in_files", async {
+        let root = project.resolve_root(root)?;
+        let matcher = LineMatcher::new(&query, case_sensitive, is_regex)?;
+
+        let mut results = Vec::new();
+        walk_tree(&root, project.root().as_deref(), &mut |path, metadata| {
+            if metadata.is_dir() {
+                return true;
+            }
+
+            let (contents, replacements) = match replace_in_file(path, &matcher, &replacement) {
+                Ok(Some(replaced)) => replaced,
+                Ok(None) => return true,
+                Err(e) => {
+                    eprintln!("Error replacing in file {}: {}", path.display(), e);
+                    return true;
+                }
+            };
+
+            let error = if dry_run {
+                None
+            } else {
+                cache.remove(path);
+                write_atomic(path, contents.as_bytes())
+                    .err()
+                    .map(|e| AppError::from_io(e, format!("Failed to write {}", path.display())))
+            };
+            results.push(ReplaceResult {
+                path: path.to_string_lossy().to_string(),
+                replacements,
+                error,
+            });
+            true
+        })?;
+
+        Ok(results)
+    })
+    .await
+}
+
+// Command for "Go to File": fuzzy-matches `query` against the relative paths
+// of every non-ignored file under `root`, best matches first. While `root` is
+// covered by watch_path the file list is cached between calls.
+#[tauri::command]
+async fn find_files_fuzzy(
+    project: tauri::State<'_, ProjectState>,
+    watcher_state: tauri::State<'_, WatcherState>,
+    index: tauri::State<'_, FileIndexState>,
+    root: Option<String>,
+    query: String,
+    limit: usize,
+) -> Result<Vec<FuzzyMatch>, AppError> {
+    report_errors("find_files_fuzzy", async {
+        let root_path = project.resolve_root(root)?;
+
+        let watched = watcher_state.watchers.lock().unwrap().keys().any(|w| root_path.starts_with(w));
+        let cached = if watched { index.files.lock().unwrap().get(&root_path).cloned() } else { None };
+        let files = match cached {
+            Some(files) => files,
+            None => {
+                let files = Arc::new(list_relative_files(&root_path, project.root().as_deref())?);
+                if watched {
+                    index.files.lock().unwrap().insert(root_path, files.clone());
+                }
+                files
+            }
+        };
+
+        let matcher = SkimMatcherV2::default();
+        let mut matches: Vec<FuzzyMatch> = files
+            .par_iter()
+            .filter_map(|file| {
+                matcher.fuzzy_indices(file, &query).map(|(score, match_positions)| FuzzyMatch {
+                    path: file.clone(),
+                    score,
+                    match_positions,
+                })
+            })
+            .collect();
+        matches.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.path.cmp(&b.path)));
+        matches.truncate(limit);
+        Ok(matches)
+    })
+    .await
+}
+
+// Command to list every non-ignored file under `root` (directories left
+// out) as paths relative to `root`
+#[tauri::command]
+async fn list_files_flat(
+    project: tauri::State<'_, ProjectState>,
+    root: Option<String>,
+) -> Result<Vec<String>, AppError> {
+    report_errors("list_files_flat", async {
+        Ok(list_relative_files(&project.resolve_root(root)?, project.root().as_deref())?)
+    })
+    .await
+}
+
+// Command to count the files list_files_flat would return
+#[tauri::command]
+async fn count_files(project: tauri::State<'_, ProjectState>, root: Option<String>) -> Result<usize, AppError> {
+    report_errors("count_files", async {
+        let mut count = 0;
+        walk_tree(&project.resolve_root(root)?, project.root().as_deref(), &mut |_, metadata| {
+            if metadata.is_file() {
+                count += 1;
+            }
+            true
+        })?;
+        Ok(count)
+    })
+    .await
+}
+
+// Command to total up the non-ignored files, directories and file bytes
+// under a root in one walk, for status-line counts without the whole tree
+#[tauri::command]
+async fn tree_counts(project: tauri::State<'_, ProjectState>, root: Option<String>) -> Result<TreeCounts, AppError> {
+    report_errors("tree_counts", async {
+        let mut counts = TreeCounts { files: 0, dirs: 0, bytes: 0 };
+        walk_tree(&project.resolve_root(root)?, project.root().as_deref(), &mut |_, metadata| {
+            if metadata.is_dir() {
+                counts.dirs += 1;
+            } else {
+                counts.files += 1;
+                counts.bytes += metadata.len();
+            }
+            true
+        })?;
+        Ok(counts)
+    })
+    .await
+}
+
+// Command to break a project down by language: the number of non-ignored
+// files and their total size per detect_language id, biggest first. Files
+// in languages we don't recognize count as "Other".
+#[tauri::command]
+async fn language_breakdown(
+    project: tauri::State<'_, ProjectState>,
+    root: Option<String>,
+) -> Result<Vec<LanguageShare>, AppError> {
+    report_errors("language_breakdown", async {
+        let mut totals: HashMap<&'static str, (usize, u64)> = HashMap::new();
+        walk_tree(&project.resolve_root(root)?, project.root().as_deref(), &mut |path, metadata| {
+            if metadata.is_file() {
+                let total = totals.entry(detect_language(path).unwrap_or("Other")).or_insert((0, 0));
+                total.0 += 1;
+                total.1 += metadata.len();
+            }
+            true
+        })?;
+
+        let mut shares: Vec<LanguageShare> = totals
+            .into_iter()
+            .map(|(language, (files, bytes))| LanguageShare { language: language.to_string(), files, bytes })
+            .collect();
+        shares.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.language.cmp(&b.language)));
+        Ok(shares)
+    })
+    .await
+}
+
+// Command to compute the SHA-256 of a file as lowercase hex
+#[tauri::command]
+async fn hash_file(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<String, AppError> {
+    report_errors("hash_file", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        Ok(digest_file::<Sha256>(&path_buf)?)
+    })
+    .await
+}
+
+// Command to compute a file's checksum with `algo` ("md5", "sha1", "sha256"
+// or "crc32") as lowercase hex, e.g. to compare against a published one
+#[tauri::command]
+async fn checksum_file(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    algo: String,
+    path_id: Option<String>,
+) -> Result<String, AppError> {
+    report_errors("checksum_file", async {
+        let path = command_path(&project, &path, path_id.as_deref())?;
+
+        Ok(match algo.to_lowercase().as_str() {
+            "md5" => digest_file::<Md5>(&path)?,
+            "sha1" => digest_file::<Sha1>(&path)?,
+            "sha256" => digest_file::<Sha256>(&path)?,
+            "crc32" => {
+                let mut hasher = crc32fast::Hasher::new();
+                read_in_chunks(&path, &mut |chunk| hasher.update(chunk))?;
+                format!("{:08x}", hasher.finalize())
+            }
+            _ => return Err(AppError::InvalidInput(format!("Unknown checksum algorithm: {}", algo))),
+        })
+    })
+    .await
+}
+
+// Command to get a file's created, modified and accessed times in unix epoch
+// millis. Each is None where the platform or filesystem doesn't record it
+// (creation time is often missing on Linux).
+#[tauri::command]
+async fn file_times(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<FileTimes, AppError> {
+    report_errors("file_times", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+
+        let metadata = std::fs::metadata(&path_buf)?;
+        Ok(FileTimes {
+            created: metadata.created().ok().and_then(to_epoch_millis),
+            modified: metadata.modified().ok().and_then(to_epoch_millis),
+            accessed: metadata.accessed().ok().and_then(to_epoch_millis),
+        })
+    })
+    .await
+}
+
+// Command to total the size of every non-ignored file under a directory.
+// Subdirectories that can't be read are skipped.
+#[tauri::command]
+async fn dir_size(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<u64, AppError> {
+    report_errors("dir_size", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+
+        let mut total = 0;
+        walk_tree(&path_buf, project.root().as_deref(), &mut |_, metadata| {
+            if metadata.is_file() {
+                total += metadata.len();
+            }
+            true
+        })?;
+        Ok(total)
+    })
+    .await
+}
+
+// Command to report the size and free space of the volume holding `path`,
+// e.g. to warn before an export that might not fit
+#[tauri::command]
+async fn disk_usage(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<DiskUsage, AppError> {
+    report_errors("disk_usage", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        let context = || format!("Failed to read disk usage for {}", path);
+
+        Ok(DiskUsage {
+            total: fs2::total_space(&path_buf).map_err(|e| AppError::from_io(e, context()))?,
+            available: fs2::available_space(&path_buf).map_err(|e| AppError::from_io(e, context()))?,
+        })
+    })
+    .await
+}
+
+// Command to get everything known about a single path in one call, for the
+// selected item's details. A symlink is described by its target where it
+// resolves, with `is_symlink` and `symlink_target` set.
+#[tauri::command]
+async fn stat_path(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<PathStat, AppError> {
+    report_errors("stat_path", async {
+        path_stat(&command_path(&project, &path, path_id.as_deref())?)
+    })
+    .await
+}
+
+// Command to stat several unrelated paths at once, e.g. a favorites list.
+// Paths are stat'ed concurrently; results come back in the order of `paths`,
+// and a missing path gets an error without affecting the others.
+#[tauri::command]
+async fn stat_paths(
+    project: tauri::State<'_, ProjectState>,
+    paths: Vec<String>,
+) -> Result<Vec<PathStatResult>, AppError> {
+    report_errors("stat_paths", async {
+        let project = project.inner();
+        Ok(paths
+            .into_par_iter()
+            .map(|path| match project.resolve_path(&path).and_then(|path_buf| path_stat(&path_buf)) {
+                Ok(stat) => PathStatResult { path, stat: Some(stat), error: None },
+                Err(e) => PathStatResult { path, stat: None, error: Some(e) },
+            })
+            .collect())
+    })
+    .await
+}
+
+// Command to check whether something exists at `path` and what it is, e.g.
+// to validate a new name before creating or renaming. A missing path is
+// reported as Missing rather than an error; only a path that can't be
+// checked (permission denied, outside the project) fails. A symlink is
+// reported as Symlink whether or not its target exists.
+#[tauri::command]
+async fn path_kind(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<PathKind, AppError> {
+    report_errors("path_kind", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        match std::fs::symlink_metadata(&path_buf) {
+            Ok(metadata) if metadata.file_type().is_symlink() => Ok(PathKind::Symlink),
+            Ok(metadata) if metadata.is_dir() => Ok(PathKind::Directory),
+            Ok(_) => Ok(PathKind::File),
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                Err(AppError::from_io(e, format!("Failed to check {}", path)))
+            }
+            // Not found, or a parent that isn't a directory
+            Err(_) => Ok(PathKind::Missing),
+        }
+    })
+    .await
+}
+
+// Command to find names that only differ by case within the same directory,
+// which collide on case-insensitive filesystems. Each group lists the
+// colliding paths.
+#[tauri::command]
+async fn find_case_collisions(
+    project: tauri::State<'_, ProjectState>,
+    root: Option<String>,
+) -> Result<Vec<Vec<String>>, AppError> {
+    report_errors("find_case_collisions", async {
+        let root = project.resolve_root(root)?;
+
+        let mut groups: HashMap<(PathBuf, String), Vec<String>> = HashMap::new();
+        walk_tree(&root, project.root().as_deref(), &mut |path, _| {
+            if let (Some(parent), Some(name)) = (path.parent(), path.file_name()) {
+                groups
+                    .entry((parent.to_path_buf(), name.to_string_lossy().to_lowercase()))
+                    .or_default()
+                    .push(path.to_string_lossy().to_string());
+            }
+            true
+        })?;
+
+        let mut collisions: Vec<Vec<String>> = groups.into_values().filter(|group| group.len() > 1).collect();
+        collisions.sort();
+        Ok(collisions)
+    })
+    .await
+}
+
+// Command to count the lines, words, characters and bytes of a text file.
+// A final line without a trailing newline still counts as a line.
+#[tauri::command]
+async fn file_stats(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<FileStats, AppError> {
+    report_errors("file_stats", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+
+        let file = std::fs::File::open(&path_buf)?;
+        let bytes = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        if looks_binary(reader.fill_buf()?) {
+            return Err(AppError::IsBinary(format!("File appears to be binary: {}", path)));
+        }
+
+        let mut stats = FileStats { lines: 0, words: 0, chars: 0, bytes };
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            stats.lines += 1;
+            stats.words += line.split_whitespace().count();
+            stats.chars += line.chars().count();
+        }
+        Ok(stats)
+    })
+    .await
+}
+
+// Command to list the symbols of a source file for the outline panel:
+// top-level functions, structs and classes, plus the methods inside classes
+// and impl blocks, in source order. Files in languages without a grammar
+// get an empty outline.
+#[tauri::command]
+async fn file_outline(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<Vec<Symbol>, AppError> {
+    report_errors("file_outline", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+
+        let language = match detect_language(&path_buf).and_then(outline_grammar) {
+            Some(language) => language,
+            None => return Ok(Vec::new()),
+        };
+        let source = read_text_file(&path_buf, MAX_READ_SIZE)?;
+
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(language)
+            .map_err(|e| AppError::Io(format!("Failed to load grammar: {}", e)))?;
+        let tree = parser.parse(&source, None)
+            .ok_or_else(|| AppError::Io(format!("Failed to parse {}", path)))?;
+
+        let mut symbols = Vec::new();
+        collect_symbols(tree.root_node(), source.as_bytes(), false, &mut symbols);
+        Ok(symbols)
+    })
+    .await
+}
+
+// Command to guess a file's indentation from the leading whitespace of its
+// first INDENT_SAMPLE_LINES non-blank lines. Tabs win if more lines start
+// with a tab than with spaces; otherwise the width is the most common step
+// between successive indent levels. Files with no indentation get the
+// default of 4 spaces with `confident: false`.
+#[tauri::command]
+async fn detect_indentation(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<Indentation, AppError> {
+    report_errors("detect_indentation", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+
+        let reader = BufReader::new(std::fs::File::open(&path_buf)?);
+        let mut tab_lines = 0;
+        let mut space_lines = 0;
+        let mut steps: HashMap<usize, usize> = HashMap::new();
+        let mut previous_indent = 0;
+
+        let lines = reader.lines()
+            .map_while(Result::ok)
+            .filter(|line| !line.trim().is_empty())
+            .take(INDENT_SAMPLE_LINES);
+        for line in lines {
+            if line.starts_with('\t') {
+                tab_lines += 1;
+                continue;
+            }
+            let indent = line.len() - line.trim_start_matches(' ').len();
+            if indent > 0 {
+                space_lines += 1;
+            }
+            if indent > previous_indent {
+                *steps.entry(indent - previous_indent).or_insert(0) += 1;
+            }
+            previous_indent = indent;
+        }
+
+        if tab_lines == 0 && space_lines == 0 {
+            return Ok(Indentation { uses_tabs: false, width: 4, confident: false });
+        }
+        if tab_lines > space_lines {
+            return Ok(Indentation { uses_tabs: true, width: 4, confident: true });
+        }
+
+        let width = steps.into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+            .map(|(step, _)| step)
+            .unwrap_or(4);
+        Ok(Indentation { uses_tabs: false, width, confident: true })
+    })
+    .await
+}
+
+// Command to count each style of line break in a file, streaming it once.
+// `dominant` is "lf", "crlf" or "cr", whichever is most common (LF wins ties,
+// then CRLF), or "none" for a file without line breaks.
+#[tauri::command]
+async fn detect_line_endings(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<LineEndingCounts, AppError> {
+    report_errors("detect_line_endings", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+
+        let (mut lf, mut crlf, mut cr) = (0, 0, 0);
+        let mut after_cr = false;
+        read_in_chunks(&path_buf, &mut |chunk| {
+            for &byte in chunk {
+                match (after_cr, byte) {
+                    (true, b'\n') => crlf += 1,
+                    // The previous CR wasn't part of a CRLF
+                    (true, _) => cr += 1,
+                    (false, b'\n') => lf += 1,
+                    (false, _) => {}
+                }
+                after_cr = byte == b'\r';
+            }
+        })?;
+        if after_cr {
+            cr += 1;
+        }
+
+        let dominant = if lf == 0 && crlf == 0 && cr == 0 {
+            "none"
+        } else if lf >= crlf && lf >= cr {
+            "lf"
+        } else if crlf >= cr {
+            "crlf"
+        } else {
+            "cr"
+        };
+        Ok(LineEndingCounts { lf, crlf, cr, dominant: dominant.to_string() })
+    })
+    .await
+}
+
+// Command to compute a line diff between two text files. Line endings are
+// normalized first, so a CRLF/LF difference alone doesn't show up as a change.
+#[tauri::command]
+async fn diff_files(
+    project: tauri::State<'_, ProjectState>,
+    left: String,
+    right: String,
+) -> Result<Vec<DiffLine>, AppError> {
+    report_errors("diff_files", async {
+        let left_path = project.resolve_path(&left)?;
+        let right_path = project.resolve_path(&right)?;
+
+        let left_text = read_text_file(&left_path, MAX_READ_SIZE)?.replace("\r\n", "\n");
+        let right_text = read_text_file(&right_path, MAX_READ_SIZE)?.replace("\r\n", "\n");
+
+        let diff = TextDiff::from_lines(&left_text, &right_text);
+        Ok(diff
+            .iter_all_changes()
+            .map(|change| DiffLine {
+                tag: match change.tag() {
+                    ChangeTag::Equal => DiffTag::Equal,
+                    ChangeTag::Insert => DiffTag::Insert,
+                    ChangeTag::Delete => DiffTag::Delete,
+                },
+                left_line: change.old_index().map(|i| i + 1),
+                right_line: change.new_index().map(|i| i + 1),
+                text: change.value().trim_end_matches('\n').to_string(),
+            })
+            .collect())
+    })
+    .await
+}
+
+// Command to export a directory as a zip archive. Ignored files are left
+// out, and entries keep their paths relative to `src`.
+#[tauri::command]
+async fn zip_directory(project: tauri::State<'_, ProjectState>, src: String, dest_zip: String) -> Result<(), AppError> {
+    report_errors("zip_directory", async {
+        let src_path = project.resolve_path(&src)?;
+        let dest_path = project.resolve_path(&dest_zip)?;
+
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&dest_path)
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::AlreadyExists => AppError::AlreadyExists(format!("File already exists: {}", dest_zip)),
+                _ => AppError::from_io(e, format!("Failed to create {}", dest_zip)),
+            })?;
+
+        let result = write_zip(&src_path, &dest_path, project.root().as_deref(), file);
+        if result.is_err() {
+            let _ = std::fs::remove_file(&dest_path);
+        }
+        result
+    })
+    .await
+}
+
+// Command to extract a zip archive into `dest_dir`, creating it if needed,
+// and return the paths written. Entries with absolute paths or `..`
+// components are rejected so an archive can't write outside `dest_dir`.
+#[tauri::command]
+async fn unzip_archive(
+    project: tauri::State<'_, ProjectState>,
+    cache: tauri::State<'_, FileCacheState>,
+    zip_path: String,
+    dest_dir: String,
+) -> Result<Vec<String>, AppError> {
+    report_errors("unzip_archive", async {
+        let zip_file = project.resolve_path(&zip_path)?;
+        let dest = project.resolve_path(&dest_dir)?;
+
+        let mut archive = ZipArchive::new(BufReader::new(std::fs::File::open(&zip_file)?))?;
+        std::fs::create_dir_all(&dest)?;
+        let canonical_dest = std::fs::canonicalize(&dest)?;
+
+        let mut extracted = Vec::new();
+        for index in 0..archive.len() {
+            let mut entry = archive.by_index(index)?;
+            let relative = entry.enclosed_name()
+                .map(Path::to_path_buf)
+                .ok_or_else(|| AppError::InvalidInput(format!("Unsafe path in archive: {}", entry.name())))?;
+            let target = dest.join(relative);
+            // Also catch a symlink already in `dest` that points elsewhere
+            if !canonicalize_partial(&target)?.starts_with(&canonical_dest) {
+                return Err(AppError::InvalidInput(format!("Unsafe path in archive: {}", entry.name())));
+            }
+
+            if entry.is_dir() {
+                std::fs::create_dir_all(&target)?;
+            } else {
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                cache.remove(&target);
+                let mut output = std::fs::File::create(&target)?;
+                std::io::copy(&mut entry, &mut output)?;
+            }
+
+            #[cfg(unix)]
+            if let Some(mode) = entry.unix_mode() {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(&target, std::fs::Permissions::from_mode(mode))?;
+            }
+
+            extracted.push(target.to_string_lossy().to_string());
+        }
+        Ok(extracted)
+    })
+    .await
+}
+
+// Command to show a file in the platform file manager, selected where the
+// platform supports it (Linux just opens the containing folder)
+#[tauri::command]
+async fn reveal_in_file_manager(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("reveal_in_file_manager", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        if std::fs::symlink_metadata(&path_buf).is_err() {
+            return Err(AppError::NotFound(format!("Path does not exist: {}", path)));
+        }
+
+        #[cfg(target_os = "windows")]
+        let mut command = {
+            let mut command = std::process::Command::new("explorer");
+            command.arg(format!("/select,{}", path_buf.display()));
+            command
+        };
+        #[cfg(target_os = "macos")]
+        let mut command = {
+            let mut command = std::process::Command::new("open");
+            command.arg("-R").arg(&path_buf);
+            command
+        };
+        #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+        let mut command = {
+            // The containing folder, for folders too, like the other platforms
+            let folder = path_buf.parent().unwrap_or(&path_buf);
+            let mut command = std::process::Command::new("xdg-open");
+            command.arg(folder);
+            command
+        };
+
+        command
+            .spawn()
+            .map(|_| ())
+            .map_err(|e| AppError::from_io(e, String::from("Failed to open the file manager")))
+    })
+    .await
+}
+
+// Command to open a file in the application the OS associates with it
+#[tauri::command]
+async fn open_with_default_app(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("open_with_default_app", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        if std::fs::symlink_metadata(&path_buf).is_err() {
+            return Err(AppError::NotFound(format!("Path does not exist: {}", path)));
+        }
+
+        open::that(&path_buf)
+            .map_err(|e| AppError::from_io(e, format!("No application could open {}", path)))
+    })
+    .await
+}
+
+// Command to get the git status of changed files under `root`, keyed by path
+// relative to `root`: "M" modified, "A" added, "D" deleted, "??" untracked.
+// A root that isn't inside a git repository yields an empty map.
+#[tauri::command]
+async fn git_status(
+    project: tauri::State<'_, ProjectState>,
+    root: Option<String>,
+) -> Result<HashMap<String, String>, AppError> {
+    report_errors("git_status", async {
+        git_status_map(&project.resolve_root(root)?)
+    })
+    .await
+}
+
+// Command to read a file as it was at a git revision (anything rev-parse
+// accepts: a branch, tag, SHA or an expression like HEAD~2), e.g. to diff the
+// working copy against HEAD
+#[tauri::command]
+async fn read_file_at_revision(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    rev: String,
+    path_id: Option<String>,
+) -> Result<String, AppError> {
+    report_errors("read_file_at_revision", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        let (repo, relative) = open_repo_for(&path_buf)?;
+
+        let tree = repo.revparse_single(&rev)
+            .and_then(|object| object.peel_to_tree())
+            .map_err(|e| AppError::NotFound(format!("Unknown revision {}: {}", rev, e.message())))?;
+        let entry = tree.get_path(&relative)
+            .map_err(|_| AppError::NotFound(format!("{} did not exist at {}", path, rev)))?;
+        let blob = entry.to_object(&repo)
+            .and_then(|object| object.peel_to_blob())
+            .map_err(|_| AppError::InvalidInput(format!("{} is not a file at {}", path, rev)))?;
+
+        let bytes = blob.content();
+        if looks_binary(&bytes[..bytes.len().min(BINARY_SAMPLE_SIZE)]) {
+            return Err(AppError::IsBinary(format!("File appears to be binary: {}", path)));
+        }
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| AppError::NotUtf8(format!("File is not valid UTF-8 at {}: {}", rev, path)))
+    })
+    .await
+}
+
+// Command to get per-line blame for a tracked file
+#[tauri::command]
+async fn git_blame(
+    project: tauri::State<'_, ProjectState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<Vec<BlameLine>, AppError> {
+    report_errors("git_blame", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+
+        let (repo, relative) = open_repo_for(&path_buf)?;
+        let blame = repo.blame_file(&relative, None)
+            .map_err(|e| AppError::NotFound(format!("Can't blame {} (is it tracked?): {}", path, e.message())))?;
+
+        let mut lines = Vec::new();
+        for hunk in blame.iter() {
+            let signature = hunk.final_signature();
+            let author = signature.name().unwrap_or("").to_string();
+            let commit = hunk.final_commit_id().to_string();
+            let timestamp = signature.when().seconds();
+            for offset in 0..hunk.lines_in_hunk() {
+                lines.push(BlameLine {
+                    line: hunk.final_start_line() + offset,
+                    commit: commit.clone(),
+                    author: author.clone(),
+                    timestamp,
+                });
+            }
+        }
+        Ok(lines)
+    })
+    .await
+}
+
+// Command to start watching a directory recursively. Changes are coalesced
+// over a short window and emitted to the window as one "fs-changes" event
+// holding the whole batch, so bulk operations like a checkout don't flood
+// the frontend. `debounce_ms` sets that window for this watch (default 150,
+// at least 10); watching the same path with a different value restarts the
+// watch with the new window.
+#[tauri::command]
+async fn watch_path<R: Runtime>(
+    window: tauri::Window<R>,
+    project: tauri::State<'_, ProjectState>,
+    state: tauri::State<'_, WatcherState>,
+    path: String,
+    debounce_ms: Option<u64>,
+    path_id: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("watch_path", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        let debounce = watch_debounce(debounce_ms);
+        let mut watchers = state.watchers.lock().unwrap();
+        if watchers.get(&path_buf).is_some_and(|watch| watch.debounce == debounce) {
+            return Ok(());
+        }
+        // Anything cached before the watch started may already be stale
+        window.state::<FileIndexState>().clear();
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(&path_buf, RecursiveMode::Recursive)?;
+
+        // The thread exits once the watcher is dropped and the channel closes
+        std::thread::spawn(move || {
+            while let Some(changes) = next_change_batch(&rx, debounce) {
+                if changes.iter().any(|change| change.kind != ChangeKind::Modified) {
+                    window.state::<FileIndexState>().clear();
+                }
+                let cache = window.state::<FileCacheState>();
+                for change in &changes {
+                    cache.remove(Path::new(&change.path));
+                }
+                if let Err(e) = window.emit("fs-changes", changes) {
+                    eprintln!("Error emitting fs-changes event: {}", e);
+                }
+            }
+        });
+
+        // Replacing an earlier watch drops its watcher, which ends its thread
+        watchers.insert(path_buf, ActiveWatch { _watcher: watcher, debounce });
+        Ok(())
+    })
+    .await
+}
+
+// Command to stop watching a directory started with watch_path. `path` is
+// resolved the same way as it was there, so the same argument stops it.
+#[tauri::command]
+async fn stop_watch(
+    project: tauri::State<'_, ProjectState>,
+    state: tauri::State<'_, WatcherState>,
+    index: tauri::State<'_, FileIndexState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("stop_watch", async {
+        index.clear();
+        match remove_watch(&state.watchers, &project, &path, path_id.as_deref())? {
+            Some(_) => Ok(()),
+            None => Err(AppError::NotFound(format!("Path is not being watched: {}", path))),
+        }
+    })
+    .await
+}
+
+// Command to list everything the backend is currently watching: directories
+// from watch_path, files from watch_file and files being tailed, sorted
+#[tauri::command]
+async fn list_watched_paths(state: tauri::State<'_, WatcherState>) -> Result<Vec<String>, AppError> {
+    report_errors("list_watched_paths", async {
+        let mut paths: Vec<PathBuf> = Vec::new();
+        paths.extend(state.watchers.lock().unwrap().keys().cloned());
+        paths.extend(state.files.lock().unwrap().keys().cloned());
+        paths.extend(state.tails.lock().unwrap().keys().cloned());
+        let mut paths: Vec<String> = paths.iter().map(|path| path.to_string_lossy().to_string()).collect();
+        paths.sort();
+        paths.dedup();
+        Ok(paths)
+    })
+    .await
+}
+
+// Command to watch a single open file and emit "file-external-change" when
+// it changes on disk. Events are coalesced over `debounce_ms` as in
+// watch_path. Watching a file that's already watched with the same window
+// does nothing.
+#[tauri::command]
+async fn watch_file<R: Runtime>(
+    window: tauri::Window<R>,
+    project: tauri::State<'_, ProjectState>,
+    state: tauri::State<'_, WatcherState>,
+    path: String,
+    debounce_ms: Option<u64>,
+    path_id: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("watch_file", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        let debounce = watch_debounce(debounce_ms);
+        let mut files = state.files.lock().unwrap();
+        if files.get(&path_buf).is_some_and(|watch| watch.debounce == debounce) {
+            return Ok(());
+        }
+
+        // Editors often save by writing a temp file and renaming it over the
+        // original, which replaces the inode, so watch the parent directory and
+        // pick out events for this file
+        let parent = path_buf.parent().map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("."));
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(&parent, RecursiveMode::NonRecursive)?;
+
+        let watched = path_buf.clone();
+        std::thread::spawn(move || {
+            let file_name = watched.file_name().map(|n| n.to_os_string());
+            while let Some(changes) = next_change_batch(&rx, debounce) {
+                let touched = changes.iter().any(|change| {
+                    Path::new(&change.path).file_name().map(|n| n.to_os_string()) == file_name
+                });
+                if touched {
+                    let payload = FileChanged { path: watched.to_string_lossy().to_string() };
+                    if let Err(e) = window.emit("file-external-change", payload) {
+                        eprintln!("Error emitting file-external-change event: {}", e);
+                    }
+                }
+            }
+        });
+
+        files.insert(path_buf, ActiveWatch { _watcher: watcher, debounce });
+        Ok(())
+    })
+    .await
+}
+
+// Command to stop watching a file started with watch_file
+#[tauri::command]
+async fn unwatch_file(
+    project: tauri::State<'_, ProjectState>,
+    state: tauri::State<'_, WatcherState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("unwatch_file", async {
+        match remove_watch(&state.files, &project, &path, path_id.as_deref())? {
+            Some(_) => Ok(()),
+            None => Err(AppError::NotFound(format!("File is not being watched: {}", path))),
+        }
+    })
+    .await
+}
+
+// Command to follow a file like `tail -f`: whatever is appended from now on
+// is emitted as "tail-data" events. If the file shrinks (truncated or
+// rotated) reading starts over from the beginning.
+#[tauri::command]
+async fn tail_file<R: Runtime>(
+    window: tauri::Window<R>,
+    project: tauri::State<'_, ProjectState>,
+    state: tauri::State<'_, WatcherState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("tail_file", async {
+        let path_buf = command_path(&project, &path, path_id.as_deref())?;
+        let mut tails = state.tails.lock().unwrap();
+        if tails.contains_key(&path_buf) {
+            return Ok(());
+        }
+
+        let mut offset = std::fs::metadata(&path_buf)?.len();
+
+        // Watch the parent directory so a rotated log that's recreated under the
+        // same name keeps being followed
+        let parent = path_buf.parent().map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("."));
+        let (tx, rx) = std::sync::mpsc::channel::<notify::Result<notify::Event>>();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(&parent, RecursiveMode::NonRecursive)?;
+
+        let tailed = path_buf.clone();
+        std::thread::spawn(move || {
+            let file_name = tailed.file_name().map(|n| n.to_os_string());
+            for result in rx {
+                let touches_file = match result {
+                    Ok(event) => event.paths.iter().any(|p| p.file_name().map(|n| n.to_os_string()) == file_name),
+                    Err(e) => {
+                        eprintln!("Error watching {}: {}", tailed.display(), e);
+                        false
+                    }
+                };
+                if !touches_file {
+                    continue;
+                }
+
+                match read_appended(&tailed, &mut offset) {
+                    Ok(Some(chunk)) => {
+                        let payload = TailData { path: tailed.to_string_lossy().to_string(), chunk };
+                        if let Err(e) = window.emit("tail-data", payload) {
+                            eprintln!("Error emitting tail-data event: {}", e);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => eprintln!("Error reading {}: {}", tailed.display(), e),
+                }
+            }
+        });
+
+        tails.insert(path_buf, watcher);
+        Ok(())
+    })
+    .await
+}
+
+// Command to stop following a file started with tail_file
+#[tauri::command]
+async fn stop_tail(
+    project: tauri::State<'_, ProjectState>,
+    state: tauri::State<'_, WatcherState>,
+    path: String,
+    path_id: Option<String>,
+) -> Result<(), AppError> {
+    report_errors("stop_tail", async {
+        match remove_watch(&state.tails, &project, &path, path_id.as_deref())? {
+            Some(_) => Ok(()),
+            None => Err(AppError::NotFound(format!("File is not being tailed: {}", path))),
+        }
+    })
+    .await
+}
+
+// Handle to the running app, so errors can be reported from anywhere
+static APP_HANDLE: OnceLock<tauri::AppHandle> = OnceLock::new();
+
+// Payload of the "backend-error" event. `command` is the failing command's
+// name, or "panic" for a panicking thread; `timestamp` is in epoch millis.
+#[derive(serde::Serialize, Clone, Debug)]
+struct BackendError {
+    command: String,
+    message: String,
+    timestamp: Option<u64>,
+}
+
+// Helper function to run a command's body and, if it fails, report the error
+// as a "backend-error" event before returning it. Cancellations are asked
+// for by the user, so they aren't reported.
+async fn report_errors<T>(
+    command: &str,
+    body: impl Future<Output = Result<T, AppError>>,
+) -> Result<T, AppError> {
+    let result = body.await;
+    match &result {
+        Err(AppError::Cancelled(_)) | Ok(_) => {}
+        Err(e) => report_backend_error(command, e.to_string()),
+    }
+    result
+}
+
+// Helper function to emit a "backend-error" event to every window
+fn report_backend_error(command: &str, message: String) {
+    let Some(app) = APP_HANDLE.get() else {
+        return;
+    };
+    let error = BackendError {
+        command: command.to_string(),
+        message,
+        timestamp: to_epoch_millis(SystemTime::now()),
+    };
+    if let Err(e) = app.emit_all("backend-error", error) {
+        eprintln!("Error emitting backend-error event: {}", e);
+    }
+}
+
+// Errors returned by commands. Serialized as `{ kind, message }` so the
+// frontend can branch on `kind` rather than matching on message text.
+#[derive(Debug, thiserror::Error, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", content = "message")]
+enum AppError {
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    PermissionDenied(String),
+    #[error("{0}")]
+    AlreadyExists(String),
+    #[error("{0}")]
+    NotUtf8(String),
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("{0}")]
+    TooLarge(String),
+    #[error("{0}")]
+    Cancelled(String),
+    #[error("{0}")]
+    IsBinary(String),
+    #[error("{0}")]
+    IsDirectory(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    Unsupported(String),
+    #[error("{0}")]
+    Io(String),
+}
+
+impl AppError {
+    // Maps an IO error to the matching variant, prefixing its message with `context`
+    fn from_io(e: std::io::Error, context: String) -> AppError {
+        AppError::from_kind(e.kind(), format!("{}: {}", context, e))
+    }
+
+    fn from_kind(kind: std::io::ErrorKind, message: String) -> AppError {
+        match kind {
+            std::io::ErrorKind::NotFound => AppError::NotFound(message),
+            std::io::ErrorKind::PermissionDenied => AppError::PermissionDenied(message),
+            std::io::ErrorKind::AlreadyExists => AppError::AlreadyExists(message),
+            // read_to_string reports invalid UTF-8 as InvalidData
+            std::io::ErrorKind::InvalidData => AppError::NotUtf8(message),
+            _ => AppError::Io(message),
+        }
+    }
+}
+
+impl From<notify::Error> for AppError {
+    fn from(e: notify::Error) -> AppError {
+        match e.kind {
+            notify::ErrorKind::Io(io_error) => AppError::from(io_error),
+            notify::ErrorKind::PathNotFound => AppError::NotFound(e.to_string()),
+            _ => AppError::Io(e.to_string()),
+        }
+    }
+}
+
+impl From<git2::Error> for AppError {
+    fn from(e: git2::Error) -> AppError {
+        match e.code() {
+            git2::ErrorCode::NotFound => AppError::NotFound(e.message().to_string()),
+            git2::ErrorCode::Exists => AppError::AlreadyExists(e.message().to_string()),
+            _ => AppError::Io(e.message().to_string()),
+        }
+    }
+}
+
+impl From<zip::result::ZipError> for AppError {
+    fn from(e: zip::result::ZipError) -> AppError {
+        match e {
+            zip::result::ZipError::Io(io_error) => AppError::from(io_error),
+            _ => AppError::InvalidInput(e.to_string()),
+        }
+    }
+}
+
+impl From<image::ImageError> for AppError {
+    fn from(e: image::ImageError) -> AppError {
+        match e {
+            image::ImageError::IoError(io_error) => AppError::from(io_error),
+            image::ImageError::Unsupported(_) => AppError::Unsupported(e.to_string()),
+            _ => AppError::InvalidInput(format!("Image is corrupt or can't be decoded: {}", e)),
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> AppError {
+        AppError::from_kind(e.kind(), e.to_string())
+    }
+}
+
+// How much of a file is sampled to decide whether it's binary
+const BINARY_SAMPLE_SIZE: usize = 8 * 1024;
+
+// The largest file read_file_content loads unless told otherwise
+const MAX_READ_SIZE: u64 = 50 * 1024 * 1024;
+
+// A window of a text file returned by read_file_chunk. `start` and `end` are
+// byte offsets into the file.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+struct FileChunk {
+    start: u64,
+    end: u64,
+    total_size: u64,
+    content: String,
+}
+
+// Line ending style to save a file with
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum LineEnding {
+    Lf,
+    Crlf,
+    // Keep whichever style the existing file mostly uses
+    Preserve,
+}
+
+// Line break counts returned by detect_line_endings
+#[derive(serde::Serialize, Debug)]
+struct LineEndingCounts {
+    lf: usize,
+    crlf: usize,
+    cr: usize,
+    dominant: String,
+}
+
+// Timestamps returned by file_times
+#[derive(serde::Serialize, Debug)]
+struct FileTimes {
+    created: Option<u64>,
+    modified: Option<u64>,
+    accessed: Option<u64>,
+}
+
+// Counts returned by file_stats
+#[derive(serde::Serialize, Debug)]
+struct FileStats {
+    lines: usize,
+    words: usize,
+    chars: usize,
+    bytes: u64,
+}
+
+// How many non-blank lines detect_indentation looks at
+const INDENT_SAMPLE_LINES: usize = 1000;
+
+// Indentation style detected by detect_indentation. `width` is the tab width
+// to display with when `uses_tabs` is set.
+#[derive(serde::Serialize, Debug)]
+struct Indentation {
+    uses_tabs: bool,
+    width: usize,
+    confident: bool,
+}
+
+// What kind of declaration a Symbol is
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "lowercase")]
+enum SymbolKind {
+    Function,
+    Struct,
+    Class,
+    Method,
+}
+
+// One entry of file_outline; `line` is 1-based
+#[derive(serde::Serialize, Debug)]
+struct Symbol {
+    name: String,
+    kind: SymbolKind,
+    line: usize,
+}
+
+// Whether a diffed line is shared by both files or only in one of them
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "lowercase")]
+enum DiffTag {
+    Equal,
+    Insert,
+    Delete,
+}
+
+// One line of a diff_files result; line numbers are 1-based and missing on
+// the side the line doesn't appear in
+#[derive(serde::Serialize, Debug)]
+struct DiffLine {
+    tag: DiffTag,
+    left_line: Option<usize>,
+    right_line: Option<usize>,
+    text: String,
+}
+
+// Blame for one line of a file: the commit that last changed it, and who and
+// when (unix seconds). Line numbers are 1-based.
+#[derive(serde::Serialize, Debug)]
+struct BlameLine {
+    line: usize,
+    commit: String,
+    author: String,
+    timestamp: i64,
+}
+
+// An item delete_path moved to the trash. `trash_id` identifies it for
+// restore_from_trash, where the platform supports that.
+#[derive(serde::Serialize, Debug)]
+struct TrashedItem {
+    original_path: String,
+    trash_id: Option<String>,
+}
+
+// The outcome of moving one item in move_paths
+#[derive(serde::Serialize, Debug)]
+struct MoveResult {
+    from: String,
+    to: String,
+    error: Option<AppError>,
+}
+
+// File content returned by read_file_lossy
+#[derive(serde::Serialize, Debug)]
+struct LossyContent {
+    content: String,
+    had_invalid: bool,
+}
+
+// File content returned by read_file_with_hash
+#[derive(serde::Serialize, Debug)]
+struct HashedContent {
+    content: String,
+    sha256: String,
+    mtime: Option<u64>,
+}
+
+// File content returned by open_file_at
+#[derive(serde::Serialize, Debug)]
+struct OpenedFile {
+    content: String,
+    valid_line: bool,
+}
+
+// Payload of the "goto-location" event
+#[derive(serde::Serialize, Clone, Debug)]
+struct GotoLocation {
+    path: String,
+    line: usize,
+}
+
+// The outcome of reading one file in read_files
+#[derive(serde::Serialize, Debug)]
+struct FileReadResult {
+    path: String,
+    content: Option<String>,
+    error: Option<AppError>,
+}
+
+// Capacity and free space in bytes, as reported by disk_usage
+#[derive(serde::Serialize, Debug)]
+struct DiskUsage {
+    total: u64,
+    available: u64,
+}
+
+// Full metadata for one path, from stat_path. Times are epoch millis, and
+// None where the platform or filesystem doesn't record them.
+#[derive(serde::Serialize, Debug)]
+struct PathStat {
+    is_directory: bool,
+    is_symlink: bool,
+    size: u64,
+    readonly: bool,
+    created: Option<u64>,
+    modified: Option<u64>,
+    accessed: Option<u64>,
+    symlink_target: Option<String>,
+}
+
+// What exists at a path, from path_kind
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "lowercase")]
+enum PathKind {
+    Missing,
+    File,
+    Directory,
+    Symlink,
+}
+
+// The outcome of stat'ing one path in stat_paths
+#[derive(serde::Serialize, Debug)]
+struct PathStatResult {
+    path: String,
+    stat: Option<PathStat>,
+    error: Option<AppError>,
+}
+
+// One language's share of the project, from language_breakdown
+#[derive(serde::Serialize, Debug)]
+struct LanguageShare {
+    language: String,
+    files: usize,
+    bytes: u64,
+}
+
+// One page of read_directory_page
+#[derive(serde::Serialize, Debug)]
+struct DirectoryPage {
+    entries: Vec<FileEntry>,
+    total: usize,
+}
+
+// Counts returned by immediate_child_count
+#[derive(serde::Serialize, Debug)]
+struct ChildCounts {
+    files: usize,
+    dirs: usize,
+}
+
+// Totals returned by tree_counts
+#[derive(serde::Serialize, Debug)]
+struct TreeCounts {
+    files: usize,
+    dirs: usize,
+    bytes: u64,
+}
+
+// One file to save in write_files
+#[derive(serde::Deserialize, Debug)]
+struct FileWrite {
+    path: String,
+    content: String,
+}
+
+// The outcome of writing one file in write_files
+#[derive(serde::Serialize, Debug)]
+struct WriteResult {
+    path: String,
+    error: Option<AppError>,
+}
+
+// The outcome of removing one child in empty_directory
+#[derive(serde::Serialize, Debug)]
+struct RemoveResult {
+    path: String,
+    error: Option<AppError>,
+}
+
+// The most disk space generate_thumbnail's cache may use
+const THUMBNAIL_CACHE_CAPACITY: u64 = 100 * 1024 * 1024;
+
+// Bytes read per "file-chunk" event in stream_file
+const STREAM_CHUNK_SIZE: usize = 256 * 1024;
+
+// Payload of the "file-chunk" event
+#[derive(serde::Serialize, Clone, Debug)]
+struct StreamChunk {
+    path: String,
+    seq: usize,
+    data: String,
+}
+
+// Payload of the "file-end" event
+#[derive(serde::Serialize, Clone, Debug)]
+struct StreamEnd {
+    path: String,
+    total_chunks: usize,
+}
+
+// Bytes per row in read_file_hex
+const HEX_ROW_WIDTH: usize = 16;
+
+// The most bytes read_file_hex returns in one call
+const MAX_HEX_WINDOW: u64 = 1024 * 1024;
+
+// The most bytes read_file_chunk returns in one call, and how far it reads
+// past the window to complete a character cut off at either end
+const MAX_CHUNK_WINDOW: u64 = 4 * 1024 * 1024;
+const UTF8_LOOKAHEAD: u64 = 6;
+
+// One row of a hex dump; non-printable bytes show as `.` in `ascii`
+#[derive(serde::Serialize, Debug)]
+struct HexRow {
+    address: u64,
+    bytes: Vec<u8>,
+    ascii: String,
+}
+
+// A text file decoded by read_file_with_encoding, with the encoding it was detected as
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+struct EncodedContent {
+    content: String,
+    encoding: String,
+    had_bom: bool,
+}
+
+// A file's raw content, base64-encoded, with the MIME type sniffed from its magic bytes
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+struct BinaryContent {
+    mime: String,
+    data: String,
+}
+
+// A struct representing a file or directory
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default)]
+struct FileEntry {
+    // Display name and path; both are lossy if the name isn't valid UTF-8
+    name: String,
+    path: String,
+    // The exact path as base64 of its raw OS bytes, for names `path` can't
+    // round-trip. Every command taking a `path` also takes it as `path_id`
+    // (rename_path and copy_path as `from_id`), which wins over `path`.
+    path_id: String,
+    // `path` relative to the root the listing started from, `/`-separated;
+    // empty for the root itself
+    relative_path: String,
+    is_directory: bool,
+    // Size in bytes; always 0 for directories
+    size: u64,
+    // Last modification time in unix epoch millis, if the platform reports it
+    modified: Option<u64>,
+    // Whether the current user can't write to the entry
+    readonly: bool,
+    // Whether the entry is a symbolic link, and where it points as stored in
+    // the link. The other fields describe the link's target; a symlinked
+    // directory is expanded like any other unless it leads back to one of
+    // its ancestors.
+    is_symlink: bool,
+    symlink_target: Option<String>,
+    // Language identifier for syntax highlighting, for files we recognize
+    language: Option<String>,
+    // Which icon to show; see icon_hint for the categories
+    icon_hint: String,
+    // The file's git status code as in git_status ("M", "A", "D", "??") when
+    // the tree was asked for with `with_git`; None for unchanged files and
+    // directories
+    git_status: Option<String>,
+    // Whether the directory has any (non-ignored) entries, even when
+    // `children` hasn't been loaded
+    has_children: bool,
+    children: Option<Vec<FileEntry>>,
+    // Set when a directory couldn't be read completely, e.g. permission
+    // denied; `children` then holds whatever could be listed
+    error: Option<AppError>,
+}
+
+// A file matched by find_files_fuzzy. `match_positions` are the character
+// indices in `path` (relative to the search root) that matched the query.
+#[derive(serde::Serialize, Debug)]
+struct FuzzyMatch {
+    path: String,
+    score: i64,
+    match_positions: Vec<usize>,
+}
+
+// The most hits search_in_files returns, so huge result sets don't flood the frontend
+const MAX_SEARCH_HITS: usize = 5000;
+
+// A single matching line found by search_in_files. Line numbers and columns
+// are 1-based; the column counts characters, not bytes.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+struct SearchHit {
+    path: String,
+    line_number: usize,
+    line_text: String,
+    column: usize,
+}
+
+// Payload of the "search-done" event
+#[derive(serde::Serialize, Clone, Debug)]
+struct SearchDone {
+    total: usize,
+    cancelled: bool,
+}
+
+// Shared flag that lets cancel_search stop a running search_in_files_stream
+#[derive(Default)]
+struct SearchState {
+    cancelled: AtomicBool,
+}
+
+// One file changed (or, in a dry run, that would be changed) by replace_in_files
+#[derive(serde::Serialize, Debug)]
+struct ReplaceResult {
+    path: String,
+    replacements: usize,
+    error: Option<AppError>,
+}
+
+// Helper function to pick the folder to open from the command-line arguments:
+// the first one that isn't a flag, if it is a directory. Flags are skipped
+// since some launchers add their own (e.g. macOS's -psn_...).
+fn folder_from_args(args: impl Iterator<Item = String>) -> Option<PathBuf> {
+    args.filter(|arg| !arg.starts_with('-'))
+        .map(PathBuf::from)
+        .next()
+        .filter(|path| path.is_dir())
+}
+
+// Helper function to list the non-ignored children of `dir` in display order,
+// without filling in `has_children`. `relative_path` is based on `root`.
+fn list_children(dir: &Path, root: &Path, ignore: &IgnoreRules) -> Result<Vec<FileEntry>, AppError> {
+    Ok(list_children_page(dir, root, ignore, 0, usize::MAX)?.0)
+}
+
+// Helper function to list up to `limit` of `dir`'s children starting at
+// `offset`, in the same order as list_children, along with how many children
+// there are in total. Only the returned page is turned into FileEntry values.
+fn list_children_page(
+    dir: &Path,
+    root: &Path,
+    ignore: &IgnoreRules,
+    offset: usize,
+    limit: usize,
+) -> Result<(Vec<FileEntry>, usize), AppError> {
+    let mut children = Vec::new();
+    for entry_result in std::fs::read_dir(dir)? {
+        let child_path = entry_result?.path();
+
+        let metadata = match std::fs::metadata(&child_path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                eprintln!("Error processing file: {}", e);
+                continue;
+            }
+        };
+        if ignore.is_ignored(&child_path, metadata.is_dir()) {
+            continue;
+        }
+        children.push((child_path, metadata));
+    }
+    // Same order as sort_entries
+    children.sort_by_cached_key(|(child_path, metadata)| {
+        let name = child_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        (!metadata.is_dir(), name.to_lowercase(), name)
+    });
+
+    let total = children.len();
+    let page = children[offset.min(total)..].iter().take(limit).map(|(child_path, metadata)| {
+        let mut child = file_entry(child_path, metadata);
+        child.relative_path = relative_path(root, child_path);
+        child
+    });
+    Ok((page.collect(), total))
+}
+
+// Helper function to set `has_children` on the directories among `entries`,
+// which were listed with `ignore`
+fn fill_has_children(entries: &mut [FileEntry], ignore: &IgnoreRules) {
+    for entry in entries.iter_mut().filter(|entry| entry.is_directory) {
+        let path = decode_path_id(&entry.path_id).unwrap_or_else(|_| PathBuf::from(&entry.path));
+        entry.has_children = has_visible_children(&path, &ignore.for_dir(&path));
+    }
+}
+
+// Helper function to gather stat_path's metadata for `path`
+fn path_stat(path: &Path) -> Result<PathStat, AppError> {
+    let link_metadata = std::fs::symlink_metadata(path)
+        .map_err(|e| AppError::from_io(e, format!("Failed to read metadata for {}", path.display())))?;
+
+    let is_symlink = link_metadata.file_type().is_symlink();
+    let symlink_target = if is_symlink {
+        std::fs::read_link(path).ok().map(|target| target.to_string_lossy().to_string())
+    } else {
+        None
+    };
+    let metadata = if is_symlink {
+        std::fs::metadata(path).unwrap_or(link_metadata)
+    } else {
+        link_metadata
+    };
+
+    Ok(PathStat {
+        is_directory: metadata.is_dir(),
+        is_symlink,
+        size: if metadata.is_dir() { 0 } else { metadata.len() },
+        readonly: is_readonly(&metadata),
+        created: metadata.created().ok().and_then(to_epoch_millis),
+        modified: metadata.modified().ok().and_then(to_epoch_millis),
+        accessed: metadata.accessed().ok().and_then(to_epoch_millis),
+        symlink_target,
+    })
+}
+
+// Helper function to build a single entry without its children
+fn file_entry(path: &Path, metadata: &std::fs::Metadata) -> FileEntry {
+    let file_name = path.file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| String::from(""));
+    let symlink_target = std::fs::symlink_metadata(path)
+        .ok()
+        .filter(|link_metadata| link_metadata.file_type().is_symlink())
+        .map(|_| {
+            std::fs::read_link(path)
+                .map(|target| target.to_string_lossy().to_string())
+                .unwrap_or_default()
+        });
+
+    FileEntry {
+        name: file_name,
+        path: path.to_string_lossy().to_string(),
+        path_id: encode_path_id(path),
+        relative_path: String::new(),
+        is_directory: metadata.is_dir(),
+        size: if metadata.is_dir() { 0 } else { metadata.len() },
+        modified: metadata.modified().ok().and_then(to_epoch_millis),
+        readonly: is_readonly(metadata),
+        is_symlink: symlink_target.is_some(),
+        symlink_target,
+        language: if metadata.is_dir() { None } else { detect_language(path).map(String::from) },
+        icon_hint: icon_hint(path, metadata.is_dir()).to_string(),
+        git_status: None,
+        has_children: false,
+        children: None,
+        error: None,
+    }
+}
+
+// Helper function to tell whether the current user lacks write permission.
+// On Unix the owner/group/other bits are checked against the effective uid
+// and gid (supplementary groups aren't considered); elsewhere this is the
+// platform's read-only attribute.
+fn is_readonly(metadata: &std::fs::Metadata) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        // SAFETY: geteuid and getegid can't fail and have no preconditions
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        if uid == 0 {
+            return false;
+        }
+        let write_bit = if metadata.uid() == uid {
+            0o200
+        } else if metadata.gid() == gid {
+            0o020
+        } else {
+            0o002
+        };
+        metadata.mode() & write_bit == 0
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.permissions().readonly()
+    }
+}
+
+// Helper function to classify an entry into one of the icon categories the
+// frontend has icons for. The set is fixed so the UI can rely on it:
+// "rust", "javascript" (JS and TS), "image", "archive", "config", "folder",
+// "folder-open" (a directory whose children are included) and "generic".
+fn icon_hint(path: &Path, is_dir: bool) -> &'static str {
+    if is_dir {
+        return "folder";
+    }
+
+    let file_name = path.file_name().map(|s| s.to_string_lossy().to_lowercase()).unwrap_or_default();
+    match file_name.as_str() {
+        "dockerfile" | "containerfile" | "makefile" | ".env" | ".gitignore" | ".dockerignore"
+        | ".deepcodeignore" | ".editorconfig" | ".npmrc" | ".prettierrc" | ".eslintrc" => return "config",
+        name if name.starts_with(".env.") => return "config",
+        _ => {}
+    }
+
+    let extension = path.extension().map(|s| s.to_string_lossy().to_lowercase()).unwrap_or_default();
+    match extension.as_str() {
+        "rs" => "rust",
+        "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" | "mts" | "cts" => "javascript",
+        "png" | "jpg" | "jpeg" | "gif" | "bmp" | "webp" | "svg" | "ico" | "tif" | "tiff" | "avif" => "image",
+        "zip" | "tar" | "gz" | "tgz" | "bz2" | "xz" | "zst" | "7z" | "rar" | "jar" => "archive",
+        "json" | "toml" | "yaml" | "yml" | "ini" | "cfg" | "conf" | "env" | "lock" => "config",
+        _ => "generic",
+    }
+}
+
+// Helper function to map a file to the language id the editor highlights it
+// as, by well-known file name first and then by extension
+fn detect_language(path: &Path) -> Option<&'static str> {
+    let file_name = path.file_name()?.to_string_lossy();
+    let by_name = match file_name.as_ref() {
+        "Dockerfile" | "Containerfile" => Some("dockerfile"),
+        "Makefile" | "GNUmakefile" | "makefile" => Some("makefile"),
+        "CMakeLists.txt" => Some("cmake"),
+        "Gemfile" | "Rakefile" => Some("ruby"),
+        ".gitignore" | ".dockerignore" | ".deepcodeignore" => Some("ignore"),
+        ".bashrc" | ".zshrc" | ".profile" => Some("shell"),
+        _ => None,
+    };
+    if by_name.is_some() {
+        return by_name;
+    }
+
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    let language = match extension.as_str() {
+        "rs" => "rust",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "typescriptreact",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "javascriptreact",
+        "py" | "pyi" => "python",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "swift" => "swift",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "rb" => "ruby",
+        "php" => "php",
+        "sh" | "bash" | "zsh" => "shell",
+        "ps1" => "powershell",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "less" => "less",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "xml" | "svg" => "xml",
+        "md" | "markdown" => "markdown",
+        "vue" => "vue",
+        "svelte" => "svelte",
+        "lua" => "lua",
+        "dart" => "dart",
+        "scala" => "scala",
+        "hs" => "haskell",
+        "ex" | "exs" => "elixir",
+        "dockerfile" => "dockerfile",
+        _ => return None,
+    };
+    Some(language)
+}
+
+// Helper function to check whether a directory has at least one entry that
+// isn't ignored, without listing all of it
+fn has_visible_children(dir: &Path, ignore: &IgnoreRules) -> bool {
+    match std::fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|entry_result| entry_result.ok()).any(|child_entry| {
+            let is_dir = child_entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            !ignore.is_ignored(&child_entry.path(), is_dir)
+        }),
+        Err(_) => false,
+    }
+}
+
+// Helper function to order entries like a file explorer: directories first,
+// then by case-insensitive name. read_dir order is filesystem-dependent, so
+// names differing only in case fall back to an exact comparison to keep the
+// order deterministic.
+fn sort_entries(entries: &mut [FileEntry]) {
+    entries.sort_by(|a, b| {
+        b.is_directory.cmp(&a.is_directory)
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+}
+
+// How many entries get_file_tree scans between progress events
+const PROGRESS_INTERVAL: usize = 500;
+
+// Payload of the "tree-progress" event
+#[derive(serde::Serialize, Clone, Debug)]
+struct TreeProgress {
+    scanned: usize,
+    current_path: String,
+}
+
+// State shared by every branch of one build_file_tree walk
+#[derive(Default)]
+struct TreeScan<'a> {
+    // The directory the walk started from, which relative paths are based on
+    root: PathBuf,
+    // Number of entries visited so far
+    scanned: AtomicUsize,
+    // Called every PROGRESS_INTERVAL entries with the count and current path
+    on_progress: Option<&'a (dyn Fn(usize, &Path) + Sync)>,
+    // Checked before each entry; once set the walk stops with Cancelled
+    cancelled: Option<&'a AtomicBool>,
+    // Leave out directories whose listed children all got filtered away.
+    // Directories past the depth limit and unreadable ones are kept.
+    prune_empty: bool,
+    // Once more entries than this have been visited the walk stops with TooLarge
+    max_entries: Option<usize>,
+    // Git status codes keyed by path relative to `root`, as from
+    // git_status_map, to fill in each entry's git_status
+    git_statuses: Option<HashMap<String, String>>,
+}
+
+// Shared flag that lets cancel_tree_scan stop a running get_file_tree
+#[derive(Default)]
+struct ScanState {
+    cancelled: AtomicBool,
+}
+
+// Helper function to build file tree recursively. `visited` holds the
+// canonical paths of the directories above `path`, so a symlink pointing back
+// at an ancestor is listed but not descended into. `depth_left` is how many
+// more levels to expand, or None for no limit.
+fn build_file_tree(
+    path: PathBuf,
+    ignore: &IgnoreRules,
+    visited: &HashSet<PathBuf>,
+    depth_left: Option<usize>,
+    scan: &TreeScan,
+) -> Result<FileEntry, AppError> {
+    if scan.cancelled.is_some_and(|cancelled| cancelled.load(Ordering::Relaxed)) {
+        return Err(AppError::Cancelled(String::from("Tree scan was cancelled")));
+    }
+
+    let scanned = scan.scanned.fetch_add(1, Ordering::Relaxed) + 1;
+    if let Some(max_entries) = scan.max_entries.filter(|max| scanned > *max) {
+        return Err(AppError::TooLarge(format!(
+            "Stopped after scanning {} entries, more than the limit of {}",
+            scanned, max_entries
+        )));
+    }
+    if let Some(on_progress) = scan.on_progress {
+        if scanned.is_multiple_of(PROGRESS_INTERVAL) {
+            on_progress(scanned, &path);
+        }
+    }
+
+    let metadata = std::fs::metadata(&path)?;
+    let mut entry = file_entry(&path, &metadata);
+    entry.relative_path = relative_path(&scan.root, &path);
+    if let Some(statuses) = &scan.git_statuses {
+        entry.git_status = statuses.get(&entry.relative_path).cloned();
+    }
+    
+    if metadata.is_dir() && depth_left == Some(0) {
+        entry.has_children = has_visible_children(&path, &ignore.for_dir(&path));
+    } else if metadata.is_dir() {
+        let canonical = std::fs::canonicalize(&path)?;
+        if visited.contains(&canonical) {
+            return Ok(entry);
+        }
+        let mut visited = visited.clone();
+        visited.insert(canonical);
+        
+        // A directory that can't be (fully) read stays in the tree, flagged
+        // with the error, rather than disappearing
+        let ignore = ignore.for_dir(&path);
+        let mut child_paths = Vec::new();
+        let read_dir = match std::fs::read_dir(&path) {
+            Ok(read_dir) => Some(read_dir),
+            Err(e) => {
+                entry.error = Some(AppError::from(e));
+                None
+            }
+        };
+        for entry_result in read_dir.into_iter().flatten() {
+            let child_entry = match entry_result {
+                Ok(child_entry) => child_entry,
+                Err(e) => {
+                    entry.error = Some(AppError::from(e));
+                    continue;
+                }
+            };
+            let child_path = child_entry.path();
+            
+            // Skip ignored files and directories
+            let is_dir = child_entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            if ignore.is_ignored(&child_path, is_dir) {
+                continue;
+            }
+            
+            child_paths.push(child_path);
+        }
+        
+        // Subdirectories are independent, so build them in parallel. Failures
+        // are logged and skipped, except cancellation and hitting
+        // max_entries, which end the walk.
+        let results: Result<Vec<Option<FileEntry>>, AppError> = child_paths
+            .into_par_iter()
+            .map(|child_path| {
+                match build_file_tree(child_path, &ignore, &visited, depth_left.map(|d| d - 1), scan) {
+                    Ok(file_entry) => Ok(Some(file_entry)),
+                    Err(e @ (AppError::Cancelled(_) | AppError::TooLarge(_))) => Err(e),
+                    Err(e) => {
+                        eprintln!("Error processing file: {}", e);
+                        Ok(None)
+                    }
+                }
+            })
+            .collect();
+        let mut children: Vec<FileEntry> = results?.into_iter().flatten().collect();
+        // Children are built first, so pruning works bottom-up
+        if scan.prune_empty {
+            children.retain(|child| {
+                !(child.is_directory && child.error.is_none() && child.children.as_ref().is_some_and(Vec::is_empty))
+            });
+        }
+        
+        sort_entries(&mut children);
+        
+        entry.has_children = !children.is_empty();
+        entry.children = Some(children);
+        entry.icon_hint = String::from("folder-open");
+    }
+    
+    Ok(entry)
+}
+
+// Helper function to collapse each chain of directories below `entry` that
+// only hold a single subdirectory (and no files) into one node. The node
+// keeps the deepest directory's path and children, and is named after every
+// directory in the chain, e.g. `com/example/foo`. `entry` itself is never
+// merged into its children.
+fn compact_folder_chains(entry: &mut FileEntry) {
+    for child in entry.children.iter_mut().flatten() {
+        // Children are compacted first, so the only subdirectory is already
+        // the end of its chain and one merge is enough
+        compact_folder_chains(child);
+        let is_chain = child.is_directory
+            && child.error.is_none()
+            && matches!(child.children.as_deref(), Some([only]) if only.is_directory);
+        if is_chain {
+            if let Some(mut only) = child.children.take().and_then(|mut children| children.pop()) {
+                only.name = format!("{}/{}", child.name, only.name);
+                *child = only;
+            }
+        }
+    }
+}
+
+// Helper function to replay a build_file_tree walk from `root` down to just
+// above `dir`: every directory on the way adds its ignore files to `rules`
+// and joins the symlink loop guard. Building `dir` with the result gives the
+// same branch as the walk from `root` would.
+fn descend_to(root: &Path, dir: &Path, rules: IgnoreRules) -> Result<(IgnoreRules, HashSet<PathBuf>), AppError> {
+    let relative = dir.strip_prefix(root)
+        .map_err(|_| AppError::InvalidInput(format!("{} is not inside {}", dir.display(), root.display())))?;
+
+    let mut rules = rules;
+    let mut visited = HashSet::new();
+    let mut ancestor = root.to_path_buf();
+    for component in relative.components() {
+        rules = rules.for_dir(&ancestor);
+        visited.insert(std::fs::canonicalize(&ancestor)?);
+        ancestor.push(component);
+    }
+    Ok((rules, visited))
+}
+
+// Helper function to visit every non-ignored entry below `root` (files and
+// directories, but not `root` itself) with the same ignore rules and symlink
+// loop guard as build_file_tree. Ignore files above `root` are loaded up to
+// `project_root` or the git root (see for_ancestors). Returning false from
+// `visit` stops the walk.
+fn walk_tree<F>(root: &Path, project_root: Option<&Path>, visit: &mut F) -> Result<(), std::io::Error>
+where
+    F: FnMut(&Path, &std::fs::Metadata) -> bool,
+{
+    walk_dir(root, &IgnoreRules::above(root, project_root), &HashSet::new(), visit).map(|_| ())
+}
+
+// Recursive part of walk_tree; returns false once the walk has been stopped
+fn walk_dir<F>(
+    dir: &Path,
+    ignore: &IgnoreRules,
+    visited: &HashSet<PathBuf>,
+    visit: &mut F,
+) -> Result<bool, std::io::Error>
+where
+    F: FnMut(&Path, &std::fs::Metadata) -> bool,
+{
+    let canonical = std::fs::canonicalize(dir)?;
+    if visited.contains(&canonical) {
+        return Ok(true);
+    }
+    let mut visited = visited.clone();
+    visited.insert(canonical);
+
+    let ignore = ignore.for_dir(dir);
+    let mut child_paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry_result| entry_result.ok().map(|e| e.path()))
+        .collect();
+    child_paths.sort();
+
+    for child_path in child_paths {
+        let metadata = match std::fs::metadata(&child_path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                eprintln!("Error processing file: {}", e);
+                continue;
+            }
+        };
+        if ignore.is_ignored(&child_path, metadata.is_dir()) {
+            continue;
+        }
+
+        if !visit(&child_path, &metadata) {
+            return Ok(false);
+        }
+        if metadata.is_dir() {
+            match walk_dir(&child_path, &ignore, &visited, visit) {
+                Ok(true) => {}
+                Ok(false) => return Ok(false),
+                Err(e) => eprintln!("Error processing directory: {}", e),
+            }
+        }
+    }
+
+    Ok(true)
+}
+
+// What search_in_files and replace_in_files look for in each line
+enum LineMatcher {
+    // A plain, case-sensitive substring
+    Literal(String),
+    // A regular expression; case-insensitive literal queries are escaped into
+    // one too, with `expand` off so their replacement is taken verbatim
+    Regex { regex: Regex, expand: bool },
+}
+
+impl LineMatcher {
+    fn new(query: &str, case_sensitive: bool, is_regex: bool) -> Result<LineMatcher, AppError> {
+        if query.is_empty() {
+            return Err(AppError::InvalidInput(String::from("Search query must not be empty")));
+        }
+
+        if !is_regex && case_sensitive {
+            return Ok(LineMatcher::Literal(query.to_string()));
+        }
+
+        let pattern = if is_regex { query.to_string() } else { regex::escape(query) };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!case_sensitive)
+            .build()
+            .map(|regex| LineMatcher::Regex { regex, expand: is_regex })
+            .map_err(|e| AppError::InvalidInput(format!("Invalid regular expression: {}", e)))
+    }
+
+    // Returns the 1-based character column of the first match in `line`
+    fn find(&self, line: &str) -> Option<usize> {
+        let start = match self {
+            LineMatcher::Literal(needle) => line.find(needle.as_str()),
+            LineMatcher::Regex { regex, .. } => regex.find(line).map(|m| m.start()),
+        };
+        start.map(|i| line[..i].chars().count() + 1)
+    }
+
+    // Replaces every match in `line`, returning the new line and how many
+    // matches were replaced. Regex replacements may use `$1`-style groups.
+    fn replace_all(&self, line: &str, replacement: &str) -> (String, usize) {
+        match self {
+            LineMatcher::Literal(needle) => {
+                (line.replace(needle.as_str(), replacement), line.matches(needle.as_str()).count())
+            }
+            LineMatcher::Regex { regex, expand } => {
+                let count = regex.find_iter(line).count();
+                if count == 0 {
+                    return (line.to_string(), 0);
+                }
+                let replaced = if *expand {
+                    regex.replace_all(line, replacement)
+                } else {
+                    regex.replace_all(line, regex::NoExpand(replacement))
+                };
+                (replaced.into_owned(), count)
+            }
+        }
+    }
+}
+
+// Helper function to collect git status codes for the files under `root`,
+// keyed by path relative to `root`
+fn git_status_map(root: &Path) -> Result<HashMap<String, String>, AppError> {
+    let repo = match Repository::discover(root) {
+        Ok(repo) => repo,
+        Err(_) => return Ok(HashMap::new()),
+    };
+    let workdir = match repo.workdir() {
+        Some(workdir) => std::fs::canonicalize(workdir)?,
+        None => return Ok(HashMap::new()),
+    };
+    let root = std::fs::canonicalize(root)?;
+
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true);
+
+    let mut statuses = HashMap::new();
+    for entry in repo.statuses(Some(&mut options))?.iter() {
+        let code = match git_status_code(entry.status()) {
+            Some(code) => code,
+            None => continue,
+        };
+        let absolute = match entry.path() {
+            Some(path) => workdir.join(path),
+            None => continue,
+        };
+        if absolute.starts_with(&root) {
+            statuses.insert(relative_path(&root, &absolute), code.to_string());
+        }
+    }
+    Ok(statuses)
+}
+
+// Helper function to open the git repository containing `path` and work out
+// the path relative to the repository's working directory. `path` needn't
+// exist any more (e.g. a file deleted since the revision being read).
+fn open_repo_for(path: &Path) -> Result<(Repository, PathBuf), AppError> {
+    let canonical = canonicalize_partial(path)?;
+    let start = canonical.ancestors().find(|ancestor| ancestor.is_dir()).unwrap_or(&canonical);
+    let repo = Repository::discover(start)
+        .map_err(|e| AppError::NotFound(format!("Not inside a git repository: {}: {}", path.display(), e.message())))?;
+    let workdir = repo.workdir()
+        .map(std::fs::canonicalize)
+        .transpose()?
+        .ok_or_else(|| AppError::InvalidInput(String::from("Repository has no working directory")))?;
+    let relative = canonical.strip_prefix(&workdir)
+        .map(Path::to_path_buf)
+        .map_err(|_| AppError::InvalidInput(format!("Path is outside the repository: {}", path.display())))?;
+    Ok((repo, relative))
+}
+
+// Helper function to reduce git2 status flags to the short code shown in the tree
+fn git_status_code(status: Status) -> Option<&'static str> {
+    if status.is_wt_new() {
+        Some("??")
+    } else if status.is_index_new() {
+        Some("A")
+    } else if status.is_wt_deleted() || status.is_index_deleted() {
+        Some("D")
+    } else if status.intersects(
+        Status::WT_MODIFIED | Status::INDEX_MODIFIED | Status::WT_RENAMED
+            | Status::INDEX_RENAMED | Status::WT_TYPECHANGE | Status::INDEX_TYPECHANGE,
+    ) {
+        Some("M")
+    } else {
+        None
+    }
+}
+
+// Helper function to list every non-ignored file under `root` as a relative path
+fn list_relative_files(root: &Path, project_root: Option<&Path>) -> Result<Vec<String>, std::io::Error> {
+    let mut files = Vec::new();
+    walk_tree(root, project_root, &mut |path, metadata| {
+        if metadata.is_file() {
+            files.push(relative_path(root, path));
+        }
+        true
+    })?;
+    Ok(files)
+}
+
+// Helper function to express `path` relative to `root` with `/` separators
+// on every platform, so it can be used as a stable key by the frontend. A
+// path outside `root`, or any path when `root` is empty because no project
+// is open, is returned as it is.
+fn relative_path(root: &Path, path: &Path) -> String {
+    match path.strip_prefix(root) {
+        Ok(relative) if !root.as_os_str().is_empty() => relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        _ => path.to_string_lossy().to_string(),
+    }
+}
+
+// Helper function to search one file line by line, appending matches to `hits`.
+// Binary files are skipped.
+fn search_file(path: &Path, matcher: &LineMatcher, hits: &mut Vec<SearchHit>) -> Result<(), std::io::Error> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+    if looks_binary(reader.fill_buf()?) {
+        return Ok(());
+    }
+
+    let mut line = String::new();
+    let mut line_number = 0;
+    while hits.len() < MAX_SEARCH_HITS {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        line_number += 1;
+
+        let line_text = line.trim_end_matches(['\n', '\r']);
+        if let Some(column) = matcher.find(line_text) {
+            hits.push(SearchHit {
+                path: path.to_string_lossy().to_string(),
+                line_number,
+                line_text: line_text.to_string(),
+                column,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+// Helper function to apply `matcher`'s replacement to every line of a text
+// file, returning the new contents and the number of replacements, or None if
+// the file is binary, not UTF-8, or has no matches
+fn replace_in_file(
+    path: &Path,
+    matcher: &LineMatcher,
+    replacement: &str,
+) -> Result<Option<(String, usize)>, std::io::Error> {
+    let bytes = std::fs::read(path)?;
+    if looks_binary(&bytes[..bytes.len().min(BINARY_SAMPLE_SIZE)]) {
+        return Ok(None);
+    }
+    let text = match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(_) => return Ok(None),
+    };
+
+    let mut contents = String::with_capacity(text.len());
+    let mut total = 0;
+    for line in text.split_inclusive('\n') {
+        let body = line.trim_end_matches(['\n', '\r']);
+        let (replaced, count) = matcher.replace_all(body, replacement);
+        contents.push_str(&replaced);
+        contents.push_str(&line[body.len()..]);
+        total += count;
+    }
+
+    Ok(if total > 0 { Some((contents, total)) } else { None })
+}
+
+// Helper function to pick the tree-sitter grammar for a detect_language id
+fn outline_grammar(language: &str) -> Option<tree_sitter::Language> {
+    match language {
+        "rust" => Some(tree_sitter_rust::language()),
+        "javascript" | "javascriptreact" => Some(tree_sitter_javascript::language()),
+        "typescript" => Some(tree_sitter_typescript::language_typescript()),
+        "typescriptreact" => Some(tree_sitter_typescript::language_tsx()),
+        "python" => Some(tree_sitter_python::language()),
+        _ => None,
+    }
+}
+
+// Helper function to append the outline symbols declared directly in `node`.
+// Functions inside a class or impl body (`in_body`) are methods. Wrappers
+// like `export` and decorators are looked through.
+fn collect_symbols(node: tree_sitter::Node, source: &[u8], in_body: bool, symbols: &mut Vec<Symbol>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        let kind = match child.kind() {
+            "function_item" | "function_declaration" | "function_definition" if in_body => SymbolKind::Method,
+            "function_item" | "function_declaration" | "function_definition" => SymbolKind::Function,
+            "method_definition" => SymbolKind::Method,
+            "struct_item" => SymbolKind::Struct,
+            "class_declaration" | "class_definition" => {
+                push_symbol(child, source, SymbolKind::Class, symbols);
+                if let Some(body) = child.child_by_field_name("body") {
+                    collect_symbols(body, source, true, symbols);
+                }
+                continue;
+            }
+            "impl_item" => {
+                if let Some(body) = child.child_by_field_name("body") {
+                    collect_symbols(body, source, true, symbols);
+                }
+                continue;
+            }
+            "export_statement" | "decorated_definition" => {
+                collect_symbols(child, source, in_body, symbols);
+                continue;
+            }
+            _ => continue,
+        };
+        push_symbol(child, source, kind, symbols);
+    }
+}
+
+// Helper function to record `node` as a symbol named by its `name` field
+fn push_symbol(node: tree_sitter::Node, source: &[u8], kind: SymbolKind, symbols: &mut Vec<Symbol>) {
+    if let Some(name) = node.child_by_field_name("name").and_then(|name| name.utf8_text(source).ok()) {
+        symbols.push(Symbol {
+            name: name.to_string(),
+            kind,
+            line: node.start_position().row + 1,
+        });
+    }
+}
+
+// Helper function to guess whether a sample from the start of a file is
+// binary rather than text: it is if it contains a NUL byte, or if more than
+// 30% of it is control characters that don't turn up in text
+fn looks_binary(sample: &[u8]) -> bool {
+    if sample.contains(&0) {
+        return true;
+    }
+    let control = sample
+        .iter()
+        .filter(|b| matches!(b, 0x01..=0x07 | 0x0e..=0x1a | 0x1c..=0x1f | 0x7f))
+        .count();
+    control * 10 > sample.len() * 3
+}
+
+// Helper function to sniff the first BINARY_SAMPLE_SIZE bytes of a file with looks_binary
+fn is_binary_file(path: &Path) -> Result<bool, std::io::Error> {
+    let mut sample = Vec::with_capacity(BINARY_SAMPLE_SIZE);
+    std::fs::File::open(path)?.take(BINARY_SAMPLE_SIZE as u64).read_to_end(&mut sample)?;
+    Ok(looks_binary(&sample))
+}
+
+// Helper function to feed a file through `consume` a fixed-size buffer at a
+// time rather than loading it into memory
+fn read_in_chunks(path: &Path, consume: &mut dyn FnMut(&[u8])) -> Result<(), std::io::Error> {
+    let mut file = std::fs::File::open(path)?;
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        consume(&buffer[..read]);
+    }
+}
+
+// Helper function to hash a file with any RustCrypto digest, as lowercase hex
+fn digest_file<D: Digest>(path: &Path) -> Result<String, std::io::Error> {
+    let mut hasher = D::new();
+    read_in_chunks(path, &mut |chunk| hasher.update(chunk))?;
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+// Helper function to read a UTF-8 text file of at most `max_bytes`
+fn read_text_file(path: &Path, max_bytes: u64) -> Result<String, AppError> {
+    if path.is_dir() {
+        return Err(AppError::IsDirectory(format!("Path is a directory: {}", path.display())));
+    }
+    ensure_size_within(path, max_bytes)?;
+    if is_binary_file(path)? {
+        return Err(AppError::IsBinary(format!("File appears to be binary: {}", path.display())));
+    }
+    Ok(std::fs::read_to_string(path)?)
+}
+
+// Helper function to stream every non-ignored entry under `src` into a zip
+// archive written to `file`. `dest` is skipped in case it lives inside `src`.
+fn write_zip(src: &Path, dest: &Path, project_root: Option<&Path>, file: std::fs::File) -> Result<(), AppError> {
+    let mut zip = ZipWriter::new(BufWriter::new(file));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let dest = canonicalize_partial(dest)?;
+
+    let mut failure = None;
+    walk_tree(src, project_root, &mut |path, metadata| {
+        if std::fs::canonicalize(path).is_ok_and(|p| p == dest) {
+            return true;
+        }
+        let name = relative_path(src, path);
+        let result = if metadata.is_dir() {
+            zip.add_directory(name, options).map_err(AppError::from)
+        } else {
+            zip.start_file(name, options)
+                .map_err(AppError::from)
+                .and_then(|_| {
+                    let mut input = std::fs::File::open(path)?;
+                    std::io::copy(&mut input, &mut zip)?;
+                    Ok(())
+                })
+        };
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                failure = Some(e);
+                false
+            }
+        }
+    })?;
+
+    if let Some(e) = failure {
+        return Err(e);
+    }
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+// Helper function to decide whether write_file_content may write `path`.
+// With `expected_hash` the file must still have that SHA-256 (Conflict
+// otherwise, including when it's been deleted); without it an existing file
+// is only replaced with `overwrite` (AlreadyExists otherwise).
+fn check_write_target(path: &Path, overwrite: bool, expected_hash: Option<&str>) -> Result<(), AppError> {
+    if let Some(expected) = expected_hash {
+        let current = match digest_file::<Sha256>(path) {
+            Ok(hash) => hash,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(AppError::Conflict(format!("File was deleted on disk: {}", path.display())));
+            }
+            Err(e) => return Err(AppError::from(e)),
+        };
+        if !current.eq_ignore_ascii_case(expected) {
+            return Err(AppError::Conflict(format!("File changed on disk: {}", path.display())));
+        }
+    } else if !overwrite && std::fs::symlink_metadata(path).is_ok() {
+        return Err(AppError::AlreadyExists(format!("File already exists: {}", path.display())));
+    }
+    Ok(())
+}
+
+// Helper function to re-layout JSON text for format_json. It doesn't go
+// through serde_json::Value, so keys keep their order and numbers their
+// exact text (`1e2`, `1.10`, integers of any size). The text is validated
+// first; the layout pass then copies strings and literals verbatim and only
+// rewrites the whitespace between tokens.
+fn reformat_json(text: &str, pretty: bool) -> Result<String, AppError> {
+    serde_json::from_str::<serde::de::IgnoredAny>(text).map_err(|e| {
+        AppError::InvalidInput(format!("Invalid JSON at line {}, column {}: {}", e.line(), e.column(), e))
+    })?;
+
+    let is_json_whitespace = |c: char| matches!(c, ' ' | '\t' | '\n' | '\r');
+    let mut formatted = String::with_capacity(text.len());
+    let mut depth = 0;
+    let new_line = |formatted: &mut String, depth: usize| {
+        if pretty {
+            formatted.push('\n');
+            formatted.push_str(&"  ".repeat(depth));
+        }
+    };
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                formatted.push(c);
+                while let Some(c) = chars.next() {
+                    formatted.push(c);
+                    match c {
+                        '\\' => formatted.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '{' | '[' => {
+                formatted.push(c);
+                while chars.next_if(|c| is_json_whitespace(*c)).is_some() {}
+                // Empty objects and arrays stay on one line
+                let close = if c == '{' { '}' } else { ']' };
+                if chars.next_if_eq(&close).is_some() {
+                    formatted.push(close);
+                } else {
+                    depth += 1;
+                    new_line(&mut formatted, depth);
+                }
+            }
+            '}' | ']' => {
+                depth -= 1;
+                new_line(&mut formatted, depth);
+                formatted.push(c);
+            }
+            ',' => {
+                formatted.push(c);
+                new_line(&mut formatted, depth);
+            }
+            ':' => formatted.push_str(if pretty { ": " } else { ":" }),
+            c if is_json_whitespace(c) => {}
+            // Numbers, true, false and null
+            c => formatted.push(c),
+        }
+    }
+    if pretty {
+        formatted.push('\n');
+    }
+    Ok(formatted)
+}
+
+// Helper function to strip trailing spaces and tabs from every line, leaving
+// each line's `\n` or `\r\n` (including a final newline) in place
+fn trim_line_ends(text: &str) -> String {
+    let mut trimmed = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let (body, ending) = if let Some(body) = line.strip_suffix("\r\n") {
+            (body, "\r\n")
+        } else if let Some(body) = line.strip_suffix('\n') {
+            (body, "\n")
+        } else {
+            (line, "")
+        };
+        trimmed.push_str(body.trim_end_matches([' ', '\t']));
+        trimmed.push_str(ending);
+    }
+    trimmed
+}
+
+// Helper function to rewrite every line break in `text` as `style`. Only
+// `\n` and `\r\n` count as line breaks; a lone `\r` is left untouched.
+fn normalize_line_endings(text: &str, style: LineEnding) -> String {
+    let lf = text.replace("\r\n", "\n");
+    match style {
+        LineEnding::Crlf => lf.replace('\n', "\r\n"),
+        LineEnding::Lf | LineEnding::Preserve => lf,
+    }
+}
+
+// Helper function to find whether CRLF or LF line breaks are more common in
+// `bytes`, preferring LF on a tie
+fn dominant_line_ending(bytes: &[u8]) -> LineEnding {
+    let newlines = bytes.iter().filter(|b| **b == b'\n').count();
+    let crlf = bytes.windows(2).filter(|w| *w == b"\r\n").count();
+    if crlf > newlines - crlf { LineEnding::Crlf } else { LineEnding::Lf }
+}
+
+// Helper function to look up the content of a built-in file template
+fn builtin_template(name: &str) -> Option<&'static str> {
+    let content = match name {
+        "rust-main" => "fn main() {\n    println!(\"Hello, world!\");\n}\n",
+        "rust-lib" => "pub fn add(left: usize, right: usize) -> usize {\n    left + right\n}\n",
+        "python-main" => "def main():\n    pass\n\n\nif __name__ == \"__main__\":\n    main()\n",
+        "react-component" => concat!(
+            "interface ComponentProps {}\n",
+            "\n",
+            "export default function Component({}: ComponentProps) {\n",
+            "  return <div></div>;\n",
+            "}\n",
+        ),
+        "html-page" => concat!(
+            "<!DOCTYPE html>\n",
+            "<html lang=\"en\">\n",
+            "<head>\n",
+            "  <meta charset=\"UTF-8\">\n",
+            "  <meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">\n",
+            "  <title>Document</title>\n",
+            "</head>\n",
+            "<body>\n",
+            "</body>\n",
+            "</html>\n",
+        ),
+        "gitignore-node" => "node_modules/\ndist/\n.env\nnpm-debug.log*\n",
+        "gitignore-rust" => "/target\nCargo.lock\n",
+        "gitignore-python" => "__pycache__/\n*.py[cod]\n.venv/\n*.egg-info/\n",
+        "readme" => "# Project\n\nDescribe the project here.\n",
+        _ => return None,
+    };
+    Some(content)
+}
+
+// Helper function to refuse files bigger than `limit` bytes before reading them
+fn ensure_size_within(path: &Path, limit: u64) -> Result<(), AppError> {
+    let size = std::fs::metadata(path)?.len();
+    if size > limit {
+        return Err(AppError::TooLarge(format!(
+            "File is {} bytes, larger than the {} byte limit: {}",
+            size, limit, path.display()
+        )));
+    }
+    Ok(())
+}
+
+// Helper function to tell whether a byte continues a multibyte UTF-8 character
+fn is_utf8_continuation(byte: u8) -> bool {
+    byte & 0b1100_0000 == 0b1000_0000
+}
+
+// Helper function to pick the whole UTF-8 characters read_file_chunk returns
+// from `bytes`, read at the chunk's offset plus some lookahead. Returns how
+// many leading bytes to skip, which continue a character that began before
+// the offset, and how many bytes to keep after those: the characters that
+// fit in the first `length` bytes, or the first character if none does.
+// With `reaches_end` `bytes` runs to the end of the file.
+fn utf8_window(bytes: &[u8], length: usize, reaches_end: bool) -> (usize, usize) {
+    let skip = bytes.iter().take_while(|b| is_utf8_continuation(**b)).count();
+    let rest = &bytes[skip..];
+    let limit = length.saturating_sub(skip).min(rest.len());
+    if reaches_end && limit == rest.len() {
+        return (skip, limit);
+    }
+
+    let keep = match utf8_complete_len(&rest[..limit]) {
+        0 => utf8_complete_len(&rest[..rest.len().min(4)]).max(rest.len().min(1)),
+        keep => keep,
+    };
+    (skip, keep)
+}
+
+// Helper function to find how many leading bytes of `bytes` form complete
+// UTF-8 characters, dropping a trailing character that's been cut short
+fn utf8_complete_len(bytes: &[u8]) -> usize {
+    // A UTF-8 character is at most 4 bytes, so only the tail needs checking
+    for back in 1..=bytes.len().min(4) {
+        let index = bytes.len() - back;
+        let byte = bytes[index];
+        if is_utf8_continuation(byte) {
+            continue;
+        }
+        let char_len = match byte {
+            0x00..=0x7f => 1,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            _ => 4,
+        };
+        return if back >= char_len { bytes.len() } else { index };
+    }
+    bytes.len()
+}
+
+// Helper function to convert a filesystem timestamp to unix epoch millis
+fn to_epoch_millis(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH).ok().map(|d| d.as_millis() as u64)
+}
+
+// Files read for gitignore-style patterns in every directory walked, in
+// increasing precedence. .deepcodeignore hides things from DeepCode only.
+const IGNORE_FILE_NAMES: [&str; 2] = [".gitignore", ".deepcodeignore"];
+
+// The rules deciding what a walk leaves out: the ignore file matchers in
+// effect for a directory, ordered from the user's global git excludes and the
+// scan root down to the directory itself, plus any patterns the frontend
+// asked for
+#[derive(Clone)]
+struct IgnoreRules {
+    matchers: Vec<Arc<Gitignore>>,
+    extra: Option<Arc<ExtraPatterns>>,
+    // Don't skip dotfiles unless something else ignores them
+    show_hidden: bool,
+}
+
+// Glob patterns passed in by the frontend, matched against both an entry's
+// name and its path relative to `root`
+struct ExtraPatterns {
+    root: PathBuf,
+    globs: GlobSet,
+}
+
+// Every walk starts from the global excludes, which any .gitignore overrides
+impl Default for IgnoreRules {
+    fn default() -> IgnoreRules {
+        IgnoreRules {
+            matchers: global_gitignore().into_iter().collect(),
+            extra: None,
+            show_hidden: false,
+        }
+    }
+}
+
+impl IgnoreRules {
+    // Adds glob patterns (globset syntax) to ignore on top of everything else
+    fn with_patterns(mut self, root: &Path, patterns: &[String]) -> Result<IgnoreRules, AppError> {
+        if patterns.is_empty() {
+            return Ok(self);
+        }
+
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            let glob = Glob::new(pattern)
+                .map_err(|e| AppError::InvalidInput(format!("Invalid ignore pattern {}: {}", pattern, e)))?;
+            builder.add(glob);
+        }
+        let globs = builder.build()
+            .map_err(|e| AppError::InvalidInput(format!("Invalid ignore patterns: {}", e)))?;
+
+        self.extra = Some(Arc::new(ExtraPatterns { root: root.to_path_buf(), globs }));
+        Ok(self)
+    }
+
+    fn with_hidden(mut self, show_hidden: bool) -> IgnoreRules {
+        self.show_hidden = show_hidden;
+        self
+    }
+
+    // Returns the rules for `dir` when it's visited on its own rather than
+    // through a walk from the root: every ignore file from the project root
+    // or the enclosing git repository's root, whichever is higher, down to
+    // `dir` is loaded.
+    fn for_ancestors(dir: &Path, project_root: Option<&Path>) -> IgnoreRules {
+        IgnoreRules::above(dir, project_root).for_dir(dir)
+    }
+
+    // Same as for_ancestors but without `dir`'s own ignore files, for walks
+    // that load those themselves on the way in.
+    fn above(dir: &Path, project_root: Option<&Path>) -> IgnoreRules {
+        let git_root = dir.ancestors().find(|a| a.join(".git").exists());
+        let project_root = project_root.filter(|root| dir.starts_with(root));
+        let top = match (git_root, project_root) {
+            (Some(git_root), Some(project_root)) if git_root.starts_with(project_root) => project_root,
+            (Some(git_root), _) => git_root,
+            (None, Some(project_root)) => project_root,
+            (None, None) => dir,
+        };
+
+        let ancestors: Vec<&Path> = dir.ancestors().skip(1).take_while(|a| a.starts_with(top)).collect();
+        ancestors.iter().rev().fold(IgnoreRules::default(), |rules, ancestor| rules.for_dir(ancestor))
+    }
+
+    // Returns the rules for `dir`, adding its own ignore files if it has any.
+    // Both files in a directory form one matcher with .deepcodeignore's
+    // patterns last, so they take precedence over that directory's
+    // .gitignore and a `!pattern` there re-includes what git ignores.
+    fn for_dir(&self, dir: &Path) -> IgnoreRules {
+        let mut rules = self.clone();
+        let ignore_files: Vec<PathBuf> = IGNORE_FILE_NAMES.iter()
+            .map(|name| dir.join(name))
+            .filter(|path| path.is_file())
+            .collect();
+        if ignore_files.is_empty() {
+            return rules;
+        }
+
+        let mut builder = GitignoreBuilder::new(dir);
+        for ignore_path in &ignore_files {
+            if let Some(e) = builder.add(ignore_path) {
+                eprintln!("Error parsing {}: {}", ignore_path.display(), e);
+            }
+        }
+        match builder.build() {
+            Ok(gitignore) => rules.matchers.push(Arc::new(gitignore)),
+            Err(e) => eprintln!("Error parsing ignore files in {}: {}", dir.display(), e),
+        }
+        rules
+    }
+
+    // Whether `path` should be left out of the tree. The deepest ignore file
+    // with a matching pattern decides (so `!foo` re-includes); when no
+    // pattern matches we fall back to the built-in skips.
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let file_name = path.file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| String::from(""));
+
+        if file_name == ".git" {
+            return true;
+        }
+
+        if let Some(extra) = &self.extra {
+            let relative = relative_path(&extra.root, path);
+            if extra.globs.is_match(&file_name) || extra.globs.is_match(&relative) {
+                return true;
+            }
+        }
+
+        for gitignore in self.matchers.iter().rev() {
+            match gitignore.matched(path, is_dir) {
+                Match::None => continue,
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+            }
+        }
+
+        // Skip hidden files and special directories
+        (file_name.starts_with(".") && !self.show_hidden) || file_name == "node_modules" || file_name == "target"
+    }
+}
+
+// Helper function to load the user's global git excludes once per run. The
+// file is found the way git does: `core.excludesFile` from the git config,
+// or `$XDG_CONFIG_HOME/git/ignore` (`~/.config/git/ignore`) by default.
+fn global_gitignore() -> Option<Arc<Gitignore>> {
+    static GLOBAL: OnceLock<Option<Arc<Gitignore>>> = OnceLock::new();
+    GLOBAL
+        .get_or_init(|| {
+            let (gitignore, error) = Gitignore::global();
+            if let Some(e) = error {
+                eprintln!("Error loading global gitignore: {}", e);
+            }
+            if gitignore.is_empty() { None } else { Some(Arc::new(gitignore)) }
+        })
+        .clone()
+}
+
+// The currently opened project. Path commands refuse to touch anything
+// outside its root and the workspace's other roots, and commands that work
+// on "the project" default to the root.
+#[derive(Default)]
+struct ProjectState {
+    root: Mutex<Option<PathBuf>>,
+    // Canonical folders added with set_workspace_roots
+    workspace_roots: Mutex<Vec<PathBuf>>,
+    // Canonical paths the user picked in a file dialog, which are allowed
+    // wherever they are; for a folder, so is everything inside it
+    picked_files: Mutex<HashSet<PathBuf>>,
+    picked_folders: Mutex<Vec<PathBuf>>,
+}
+
+impl ProjectState {
+    fn root(&self) -> Option<PathBuf> {
+        self.root.lock().unwrap().clone()
+    }
+
+    // Resolves the root a project-wide command should run on: `root` when
+    // the caller passes one (it must be inside the project), otherwise the
+    // project root itself
+    fn resolve_root(&self, root: Option<String>) -> Result<PathBuf, AppError> {
+        match root {
+            Some(root) => self.resolve_path(&root),
+            None => self.root()
+                .ok_or_else(|| AppError::PermissionDenied(String::from("No project folder is open"))),
+        }
+    }
+
+    // Turns a path argument from the frontend into the path a command works
+    // on. Empty input is refused, relative paths are taken from the project
+    // root, `/` is accepted as a separator on Windows too, and the result
+    // must be inside the project. Names are taken literally: `$`, `%` and
+    // `~` are ordinary characters here (see resolve_typed_path).
+    fn resolve_path(&self, input: &str) -> Result<PathBuf, AppError> {
+        if input.trim().is_empty() {
+            return Err(AppError::InvalidInput(String::from("Path must not be empty")));
+        }
+        let input = if cfg!(windows) { input.replace('/', "\\") } else { input.to_string() };
+        self.resolve_path_buf(PathBuf::from(input))
+    }
+
+    // Like resolve_path, but for a path the user typed, e.g. into a "go to
+    // path" box: environment variables and a leading `~` are expanded first
+    // (see expand_path). An absolute path that exists as given is taken
+    // literally, so a real file named e.g. `$x` can still be reached.
+    fn resolve_typed_path(&self, input: &str) -> Result<PathBuf, AppError> {
+        let literal = Path::new(input);
+        if literal.is_absolute() && std::fs::symlink_metadata(literal).is_ok() {
+            return self.resolve_path(input);
+        }
+        self.resolve_path(&expand_path(input)?)
+    }
+
+    // Like resolve_path, for a path that didn't come in as a string (e.g. one
+    // decoded from a path_id)
+    fn resolve_path_buf(&self, path: PathBuf) -> Result<PathBuf, AppError> {
+        let path = if path.is_relative() {
+            self.root()
+                .ok_or_else(|| AppError::PermissionDenied(String::from("No project folder is open")))?
+                .join(path)
+        } else {
+            path
+        };
+        self.ensure_within_root(&path)?;
+        Ok(path)
+    }
+
+    fn set_root(&self, path: &Path) -> Result<(), AppError> {
+        let canonical = std::fs::canonicalize(path)
+            .map_err(|e| AppError::from_io(e, format!("Failed to open project {}", path.display())))?;
+        if !canonical.is_dir() {
+            return Err(AppError::InvalidInput(format!("Not a directory: {}", path.display())));
+        }
+
+        *self.root.lock().unwrap() = Some(canonical);
+        Ok(())
+    }
+
+    // Allows `path`, chosen by the user in a dialog, past ensure_within_root.
+    // With `is_folder` everything inside it is allowed too.
+    fn allow_picked(&self, path: &Path, is_folder: bool) -> Result<(), AppError> {
+        let canonical = canonicalize_partial(path)?;
+        if is_folder {
+            self.picked_folders.lock().unwrap().push(canonical);
+        } else {
+            self.picked_files.lock().unwrap().insert(canonical);
+        }
+        Ok(())
+    }
+
+    // Replaces the workspace roots. Nothing changes if any of them isn't a
+    // directory.
+    fn set_workspace_roots<'a>(&self, roots: impl Iterator<Item = &'a Path>) -> Result<(), AppError> {
+        let mut canonical_roots = Vec::new();
+        for root in roots {
+            let canonical = std::fs::canonicalize(root)
+                .map_err(|e| AppError::from_io(e, format!("Failed to open workspace root {}", root.display())))?;
+            if !canonical.is_dir() {
+                return Err(AppError::InvalidInput(format!("Not a directory: {}", root.display())));
+            }
+            canonical_roots.push(canonical);
+        }
+
+        *self.workspace_roots.lock().unwrap() = canonical_roots;
+        Ok(())
+    }
+
+    // Checks that `path` resolves to a location inside the project root or
+    // one of the workspace roots, or to something picked in a dialog. Both
+    // `..` segments and symlinks (dangling ones too) are resolved before
+    // comparing, so neither can be used to escape a root. The file a write
+    // to `path` would replace (see symlink_destination) is checked as well.
+    fn ensure_within_root(&self, path: &Path) -> Result<(), AppError> {
+        let mut roots = self.workspace_roots.lock().unwrap().clone();
+        roots.extend(self.picked_folders.lock().unwrap().iter().cloned());
+        roots.extend(self.root());
+        let picked_files = self.picked_files.lock().unwrap();
+        if roots.is_empty() && picked_files.is_empty() {
+            return Err(AppError::PermissionDenied(String::from("No project folder is open")));
+        }
+
+        let allowed = |resolved: &PathBuf| {
+            roots.iter().any(|root| resolved.starts_with(root)) || picked_files.contains(resolved)
+        };
+        if allowed(&canonicalize_partial(path)?) && allowed(&canonicalize_partial(&symlink_destination(path)?)?) {
+            Ok(())
+        } else {
+            Err(AppError::PermissionDenied(format!("Path is outside the project: {}", path.display())))
+        }
+    }
+}
+
+// Helper function to encode a path's raw OS bytes (UTF-16 code units on
+// Windows) as base64, so names that aren't valid UTF-8 survive the trip
+// through JSON
+fn encode_path_id(path: &Path) -> String {
+    #[cfg(unix)]
+    let bytes = {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().to_vec()
+    };
+    #[cfg(windows)]
+    let bytes: Vec<u8> = {
+        use std::os::windows::ffi::OsStrExt;
+        path.as_os_str().encode_wide().flat_map(u16::to_le_bytes).collect()
+    };
+    #[cfg(not(any(unix, windows)))]
+    let bytes = path.to_string_lossy().as_bytes().to_vec();
+
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+// Helper function to turn an encode_path_id identifier back into the path
+fn decode_path_id(id: &str) -> Result<PathBuf, AppError> {
+    let bytes = base64::engine::general_purpose::STANDARD.decode(id)
+        .map_err(|e| AppError::InvalidInput(format!("Invalid path id: {}", e)))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        Ok(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStringExt;
+        if bytes.len() % 2 != 0 {
+            return Err(AppError::InvalidInput(String::from("Invalid path id: odd length")));
+        }
+        let wide: Vec<u16> = bytes.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+        Ok(PathBuf::from(std::ffi::OsString::from_wide(&wide)))
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        String::from_utf8(bytes)
+            .map(PathBuf::from)
+            .map_err(|_| AppError::InvalidInput(String::from("Invalid path id: not UTF-8")))
+    }
+}
+
+// Helper function to resolve the path a command works on: the one `path_id`
+// encodes when the caller has it, otherwise `path`. Every command taking a
+// single existing path goes through this.
+fn command_path(project: &ProjectState, path: &str, path_id: Option<&str>) -> Result<PathBuf, AppError> {
+    match path_id {
+        Some(id) => project.resolve_path_buf(decode_path_id(id)?),
+        None => project.resolve_path(path),
+    }
+}
+
+// Helper function to format a canonical path for the UI. On Windows
+// canonicalize returns verbatim paths (`\\?\C:\...`, `\\?\UNC\server\...`),
+// so the prefix is stripped back to the usual form.
+fn display_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if cfg!(windows) {
+        if let Some(rest) = path.strip_prefix(r"\\?\UNC\") {
+            return format!(r"\\{}", rest);
+        }
+        if let Some(rest) = path.strip_prefix(r"\\?\") {
+            return rest.to_string();
+        }
+    }
+    path.to_string()
+}
+
+// Helper function to expand a leading `~` to the home directory and
+// environment variables (`$VAR` and `${VAR}`, or `%VAR%` on Windows) in a
+// typed-in path. A variable that isn't set is an error rather than being
+// left in as a literal directory name.
+fn expand_path(input: &str) -> Result<String, AppError> {
+    let mut expanded = String::with_capacity(input.len());
+    let mut rest = input;
+    if rest == "~" || rest.starts_with("~/") || rest.starts_with("~\\") {
+        let home = dirs::home_dir()
+            .ok_or_else(|| AppError::NotFound(String::from("Can't find the home directory to expand ~")))?;
+        expanded.push_str(&home.to_string_lossy());
+        rest = &rest[1..];
+    }
+
+    let pattern = if cfg!(windows) {
+        r"%([A-Za-z_][A-Za-z0-9_()]*)%"
+    } else {
+        r"\$(?:\{([A-Za-z_][A-Za-z0-9_]*)\}|([A-Za-z_][A-Za-z0-9_]*))"
+    };
+    let variable = Regex::new(pattern).expect("variable pattern is valid");
+    let mut last = 0;
+    for captures in variable.captures_iter(rest) {
+        let whole = captures.get(0).expect("group 0 always matches");
+        let name = captures.get(1).or_else(|| captures.get(2)).map_or("", |m| m.as_str());
+        let value = std::env::var_os(name)
+            .ok_or_else(|| AppError::InvalidInput(format!("Unknown environment variable in path: {}", name)))?;
+        expanded.push_str(&rest[last..whole.start()]);
+        expanded.push_str(&value.to_string_lossy());
+        last = whole.end();
+    }
+    expanded.push_str(&rest[last..]);
+    Ok(expanded)
+}
+
+// Helper function to canonicalize a path that may not exist yet (e.g. a file
+// about to be created): the deepest existing ancestor is canonicalized and
+// the missing components are appended to it. A dangling symlink on the way
+// isn't a missing component: it's followed to wherever it points, so a
+// link leading out of the project can't pass for a new file inside it.
+fn canonicalize_partial(path: &Path) -> Result<PathBuf, AppError> {
+    let mut current = path.to_path_buf();
+    // Bounded like the OS's own limit on following links, in case of a loop
+    'follow: for _ in 0..MAX_SYMLINK_HOPS {
+        let mut existing = current.as_path();
+        let mut missing = Vec::new();
+        loop {
+            match std::fs::canonicalize(existing) {
+                Ok(canonical) => {
+                    return Ok(missing.iter().rev().fold(canonical, |acc: PathBuf, name| acc.join(name)));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    let is_symlink = std::fs::symlink_metadata(existing)
+                        .is_ok_and(|metadata| metadata.file_type().is_symlink());
+                    if is_symlink {
+                        let target = std::fs::read_link(existing)?;
+                        let base = existing.parent().map_or_else(PathBuf::new, Path::to_path_buf);
+                        current = missing.iter().rev().fold(base.join(target), |acc: PathBuf, name| acc.join(name));
+                        continue 'follow;
+                    }
+
+                    // file_name() is None for a trailing `..`, which can't be
+                    // resolved without the directory existing
+                    let name = existing.file_name()
+                        .ok_or_else(|| AppError::InvalidInput(format!("Invalid path: {}", path.display())))?;
+                    missing.push(name.to_os_string());
+                    existing = match existing.parent() {
+                        Some(parent) if !parent.as_os_str().is_empty() => parent,
+                        _ => Path::new("."),
+                    };
+                }
+                Err(e) => return Err(AppError::from(e)),
+            }
+        }
+    }
+    Err(AppError::InvalidInput(format!("Too many levels of symbolic links: {}", path.display())))
+}
+
+// How many recent projects are remembered
+const MAX_RECENT_PROJECTS: usize = 10;
+
+// Contents of recent.json in the app config dir
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default)]
+#[serde(default)]
+struct Recents {
+    projects: Vec<String>,
+    // The folder last picked in open_folder_dialog, where it opens next time
+    last_dialog_dir: Option<String>,
+}
+
+// Helper function to locate recent.json
+fn recents_path<R: Runtime>(app: &tauri::AppHandle<R>) -> Result<PathBuf, AppError> {
+    app.path_resolver()
+        .app_config_dir()
+        .map(|dir| dir.join("recent.json"))
+        .ok_or_else(|| AppError::NotFound(String::from("App config directory is unavailable")))
+}
+
+// Helper function to load recent.json; a missing or unreadable file is
+// treated as empty rather than an error
+fn load_recents<R: Runtime>(app: &tauri::AppHandle<R>) -> Recents {
+    recents_path(app)
+        .ok()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+// Helper function to write recent.json, creating the config dir if needed
+fn save_recents<R: Runtime>(app: &tauri::AppHandle<R>, recents: &Recents) -> Result<(), AppError> {
+    let path = recents_path(app)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let content = serde_json::to_string_pretty(recents)
+        .map_err(|e| AppError::Io(format!("Failed to serialize recent projects: {}", e)))?;
+    Ok(write_atomic(&path, content.as_bytes())?)
+}
+
+// How long a watcher waits for related events before emitting them, unless
+// the watch asks for something else, and the shortest window it may ask for
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(150);
+const MIN_WATCH_DEBOUNCE: Duration = Duration::from_millis(10);
+
+// Active filesystem watchers, keyed by the watched path
+#[derive(Default)]
+struct WatcherState {
+    // Directories watched with watch_path
+    watchers: Mutex<HashMap<PathBuf, ActiveWatch>>,
+    // Single files watched with watch_file
+    files: Mutex<HashMap<PathBuf, ActiveWatch>>,
+    // Files followed with tail_file
+    tails: Mutex<HashMap<PathBuf, RecommendedWatcher>>,
+}
+
+// A running watch_path or watch_file watcher and the window its events are
+// coalesced over
+struct ActiveWatch {
+    // Only held so the watch keeps running; dropping it stops the watch
+    _watcher: RecommendedWatcher,
+    debounce: Duration,
+}
+
+// Payload of the "file-external-change" event
+#[derive(serde::Serialize, Clone, Debug)]
+struct FileChanged {
+    path: String,
+}
+
+// Flat file lists per root, cached by find_files_fuzzy and cleared whenever
+// a watcher sees files appear or disappear
+#[derive(Default)]
+struct FileIndexState {
+    files: Mutex<HashMap<PathBuf, Arc<Vec<String>>>>,
+}
+
+impl FileIndexState {
+    fn clear(&self) {
+        self.files.lock().unwrap().clear();
+    }
+}
+
+// Upper bound on the total size of the files read_file_content keeps cached
+const FILE_CACHE_CAPACITY: u64 = 64 * 1024 * 1024;
+
+// Recently read file contents, evicted least recently used first once they
+// add up to more than FILE_CACHE_CAPACITY bytes
+#[derive(Default)]
+struct FileCacheState {
+    cache: Mutex<FileCache>,
+}
+
+#[derive(Default)]
+struct FileCache {
+    entries: HashMap<PathBuf, CachedFile>,
+    total_bytes: u64,
+    // Incremented on every access; an entry's `last_used` orders evictions
+    clock: u64,
+}
+
+struct CachedFile {
+    content: String,
+    // Size and mtime of the file on disk when it was read
+    len: u64,
+    modified: Option<SystemTime>,
+    last_used: u64,
+}
+
+impl FileCacheState {
+    // Returns the cached content if the file still has the size and
+    // modification time it had when it was read
+    fn get(&self, path: &Path, metadata: &std::fs::Metadata) -> Option<String> {
+        let mut cache = self.cache.lock().unwrap();
+        cache.clock += 1;
+        let clock = cache.clock;
+        let entry = cache.entries.get_mut(path)?;
+        if entry.len != metadata.len() || entry.modified != metadata.modified().ok() {
+            return None;
+        }
+        entry.last_used = clock;
+        Some(entry.content.clone())
+    }
+
+    // Caches `content` as read when the file had `metadata`, evicting old
+    // entries to make room. Files bigger than the whole cache aren't kept.
+    fn insert(&self, path: PathBuf, metadata: &std::fs::Metadata, content: &str) {
+        let len = content.len() as u64;
+        if len > FILE_CACHE_CAPACITY {
+            return;
+        }
+
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(old) = cache.entries.remove(&path) {
+            cache.total_bytes -= old.content.len() as u64;
+        }
+        while cache.total_bytes + len > FILE_CACHE_CAPACITY {
+            let oldest = cache.entries.iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(path, _)| path.clone());
+            match oldest.and_then(|oldest| cache.entries.remove(&oldest)) {
+                Some(evicted) => cache.total_bytes -= evicted.content.len() as u64,
+                None => break,
+            }
+        }
+
+        cache.clock += 1;
+        let last_used = cache.clock;
+        cache.total_bytes += len;
+        cache.entries.insert(path, CachedFile {
+            content: content.to_string(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            last_used,
+        });
+    }
+
+    fn remove(&self, path: &Path) {
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(old) = cache.entries.remove(path) {
+            cache.total_bytes -= old.content.len() as u64;
+        }
+    }
+
+    fn clear(&self) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.entries.clear();
+        cache.total_bytes = 0;
+    }
+}
+
+// Payload of the "tail-data" event
+#[derive(serde::Serialize, Clone, Debug)]
+struct TailData {
+    path: String,
+    chunk: String,
+}
+
+// Helper function to read whatever was appended to a tailed file since
+// `offset` and advance it. A character cut off at the end is left for the
+// next read. Returns None when there's nothing new.
+fn read_appended(path: &Path, offset: &mut u64) -> Result<Option<String>, std::io::Error> {
+    let mut file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        // Mid-rotation the file may briefly not exist
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let len = file.metadata()?.len();
+    if len < *offset {
+        *offset = 0;
+    }
+    if len == *offset {
+        return Ok(None);
+    }
+
+    file.seek(SeekFrom::Start(*offset))?;
+    let mut bytes = Vec::with_capacity((len - *offset) as usize);
+    file.take(len - *offset).read_to_end(&mut bytes)?;
+    bytes.truncate(utf8_complete_len(&bytes));
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+
+    *offset += bytes.len() as u64;
+    Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+// The kind of filesystem change reported to the frontend
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ChangeKind {
+    Created,
+    Modified,
+    Removed,
+}
+
+// One entry of the "fs-changes" event
+#[derive(serde::Serialize, Clone, Debug)]
+struct ChangeEvent {
+    kind: ChangeKind,
+    path: String,
+}
+
+// Helper function to take the watch for `path` out of one of WatcherState's
+// maps. The key is resolved exactly as the command that started the watch
+// resolved it, so e.g. a relative path stops what it started.
+fn remove_watch<T>(
+    watches: &Mutex<HashMap<PathBuf, T>>,
+    project: &ProjectState,
+    path: &str,
+    path_id: Option<&str>,
+) -> Result<Option<T>, AppError> {
+    let key = command_path(project, path, path_id)?;
+    Ok(watches.lock().unwrap().remove(&key))
+}
+
+// Helper function to turn a watch's `debounce_ms` into its coalescing window,
+// clamped to MIN_WATCH_DEBOUNCE so a tiny value can't busy-loop the thread
+fn watch_debounce(debounce_ms: Option<u64>) -> Duration {
+    debounce_ms.map_or(WATCH_DEBOUNCE, Duration::from_millis).max(MIN_WATCH_DEBOUNCE)
+}
+
+// Helper function to block until the next watcher event arrives, then gather
+// everything that follows within `debounce`. Repeated events for a path are
+// coalesced into one change. Returns None once the watcher has been dropped.
+fn next_change_batch(
+    rx: &std::sync::mpsc::Receiver<notify::Result<notify::Event>>,
+    debounce: Duration,
+) -> Option<Vec<ChangeEvent>> {
+    // Changes in the order their paths were first seen, plus an index into
+    // it so large batches don't need a linear scan per event
+    let mut pending: Vec<(PathBuf, ChangeKind)> = Vec::new();
+    let mut positions: HashMap<PathBuf, usize> = HashMap::new();
+    let mut record = |result: notify::Result<notify::Event>| match result {
+        Ok(event) => {
+            for (path, kind) in classify_event(&event) {
+                match positions.get(&path) {
+                    Some(&i) => {
+                        let existing = &mut pending[i].1;
+                        // A file created and then written to is still just "created"
+                        if !(*existing == ChangeKind::Created && kind == ChangeKind::Modified) {
+                            *existing = kind;
+                        }
+                    }
+                    None => {
+                        positions.insert(path.clone(), pending.len());
+                        pending.push((path, kind));
+                    }
+                }
+            }
+        }
+        Err(e) => eprintln!("Error watching files: {}", e),
+    };
+
+    record(rx.recv().ok()?);
+    let deadline = Instant::now() + debounce;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(remaining) {
+            Ok(result) => record(result),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => break,
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    Some(pending
+        .into_iter()
+        .map(|(path, kind)| ChangeEvent { kind, path: path.to_string_lossy().to_string() })
+        .collect())
+}
+
+// Helper function to turn a raw notify event into (path, kind) pairs.
+// Renames are reported as a removal of the old path and creation of the new.
+fn classify_event(event: &notify::Event) -> Vec<(PathBuf, ChangeKind)> {
+    let kind = match event.kind {
+        EventKind::Create(_) => ChangeKind::Created,
+        EventKind::Remove(_) => ChangeKind::Removed,
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => ChangeKind::Removed,
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => ChangeKind::Created,
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+            return vec![
+                (event.paths[0].clone(), ChangeKind::Removed),
+                (event.paths[1].clone(), ChangeKind::Created),
+            ];
+        }
+        EventKind::Modify(_) | EventKind::Any => ChangeKind::Modified,
+        EventKind::Access(_) | EventKind::Other => return Vec::new(),
+    };
+    event.paths.iter().map(|path| (path.clone(), kind)).collect()
+}
+
+// Helper function to look up the id of the item most recently trashed from
+// `original`. The trash crate can only list the trash on Windows and
+// freedesktop systems.
+#[cfg(any(windows, all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "android")))))]
+fn find_trash_id(original: &Path) -> Option<String> {
+    let items = match trash::os_limited::list() {
+        Ok(items) => items,
+        Err(e) => {
+            eprintln!("Error listing the trash: {}", e);
+            return None;
+        }
+    };
+    items
+        .into_iter()
+        .filter(|item| item.original_path() == original)
+        .max_by_key(|item| item.time_deleted)
+        .map(|item| item.id.to_string_lossy().to_string())
+}
+
+#[cfg(not(any(windows, all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "android"))))))]
+fn find_trash_id(_original: &Path) -> Option<String> {
+    None
+}
+
+// Helper function to restore the trashed item with `trash_id` to its original
+// location, which must be inside the project
+#[cfg(any(windows, all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "android")))))]
+fn restore_trashed(project: &ProjectState, trash_id: &str) -> Result<String, AppError> {
+    let item = trash::os_limited::list()
+        .map_err(|e| AppError::Io(format!("Failed to list the trash: {}", e)))?
+        .into_iter()
+        .find(|item| item.id.to_string_lossy() == trash_id)
+        .ok_or_else(|| AppError::NotFound(format!("Item is no longer in the trash: {}", trash_id)))?;
+    let original = project.resolve_path_buf(item.original_path())?;
+
+    trash::os_limited::restore_all([item]).map_err(|e| match e {
+        trash::Error::RestoreCollision { path, .. } => {
+            AppError::AlreadyExists(format!("Can't restore over existing {}", path.display()))
+        }
+        e => AppError::Io(format!("Failed to restore {}: {}", original.display(), e)),
+    })?;
+    Ok(original.to_string_lossy().to_string())
+}
+
+#[cfg(not(any(windows, all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "android"))))))]
+fn restore_trashed(_project: &ProjectState, _trash_id: &str) -> Result<String, AppError> {
+    Err(AppError::Unsupported(String::from("Restoring from the trash isn't supported on this platform")))
+}
+
+// Helper function to delete the least recently used thumbnails in `dir`
+// (oldest modification time first) until the rest fit in `capacity` bytes
+fn evict_thumbnails(dir: &Path, capacity: u64) -> Result<(), std::io::Error> {
+    let mut thumbnails = Vec::new();
+    for entry_result in std::fs::read_dir(dir)? {
+        let entry = entry_result?;
+        let path = entry.path();
+        // Leave temp files from writes in progress alone
+        if path.extension().is_none_or(|ext| ext != "png") {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        thumbnails.push((metadata.modified()?, metadata.len(), path));
+    }
+
+    let mut total: u64 = thumbnails.iter().map(|(_, len, _)| len).sum();
+    thumbnails.sort();
+    for (_, len, path) in thumbnails {
+        if total <= capacity {
+            break;
+        }
+        std::fs::remove_file(&path)?;
+        total -= len;
+    }
+    Ok(())
+}
+
+// Helper function to permanently delete a file or a directory with
+// everything in it. A symlink is removed itself, never what it points to.
+fn remove_entry(path: &Path) -> Result<(), std::io::Error> {
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
+
+// Helper function to move a file or directory, refusing to replace anything
+// already at `to`. Falls back to copy-then-delete across filesystems, where
+// a plain rename isn't possible.
+fn move_path(from: &Path, to: &Path) -> Result<(), AppError> {
+    if std::fs::symlink_metadata(to).is_ok() {
+        return Err(AppError::AlreadyExists(format!("Target already exists: {}", to.display())));
+    }
+
+    match std::fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(e) if is_cross_device(&e) => {
+            copy_recursive(from, to)
+                .map_err(|e| AppError::from_io(e, format!("Failed to copy {} to {}", from.display(), to.display())))?;
+            let removed = if from.is_dir() {
+                std::fs::remove_dir_all(from)
+            } else {
+                std::fs::remove_file(from)
+            };
+            removed.map_err(|e| {
+                AppError::from_io(e, format!("Copied to {} but failed to remove {}", to.display(), from.display()))
+            })
+        }
+        Err(e) => Err(AppError::from_io(e, format!("Failed to rename {} to {}", from.display(), to.display()))),
+    }
+}
+
+// Helper function to copy a file, or a directory and everything below it
+fn copy_recursive(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+    if std::fs::metadata(from)?.is_dir() {
+        std::fs::create_dir(to)?;
+        for entry_result in std::fs::read_dir(from)? {
+            let child_entry = entry_result?;
+            copy_recursive(&child_entry.path(), &to.join(child_entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        std::fs::copy(from, to).map(|_| ())
+    }
+}
+
+// Helper function to tell whether an IO error was caused by an operation
+// crossing filesystem boundaries (EXDEV / ERROR_NOT_SAME_DEVICE)
+fn is_cross_device(e: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    const CROSS_DEVICE: i32 = 18;
+    #[cfg(windows)]
+    const CROSS_DEVICE: i32 = 17;
+    #[cfg(not(any(unix, windows)))]
+    const CROSS_DEVICE: i32 = -1;
+
+    e.raw_os_error() == Some(CROSS_DEVICE)
+}
+
+// Helper function to write a file atomically: the content goes to a temp file
+// in the same directory which is then renamed over the target, so a crash
+// mid-write never leaves a truncated file behind. When `path` is a symlink
+// the file it points at is replaced and the link is left alone. Every write
+// gets its own temp file, so concurrent writes to one path can't interleave.
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), std::io::Error> {
+    let path = &symlink_destination(path)?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    if !parent.is_dir() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Parent directory does not exist: {}", parent.display()),
+        ));
+    }
+
+    let file_name = path.file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| String::from("file"));
+    let (tmp_path, mut file) = create_temp_file(&parent, &file_name)?;
+
+    let result = (|| {
+        file.write_all(contents)?;
+        file.sync_all()?;
+
+        // Keep the permissions of the file being replaced
+        if let Ok(metadata) = std::fs::metadata(path) {
+            std::fs::set_permissions(&tmp_path, metadata.permissions())?;
+        }
+
+        std::fs::rename(&tmp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+// How many symlinks canonicalize_partial and symlink_destination follow
+// before deciding there's a loop, as Linux does
+const MAX_SYMLINK_HOPS: usize = 40;
+
+// Counter making each temp file name from create_temp_file unique within the process
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Helper function to create a new, empty temp file next to `file_name` in
+// `dir`. The name includes the process id and a per-process counter, and the
+// file is created with `create_new` so two writers can never share one.
+fn create_temp_file(dir: &Path, file_name: &str) -> Result<(PathBuf, std::fs::File), std::io::Error> {
+    loop {
+        let n = TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let tmp_path = dir.join(format!(".{}.{}.{}.tmp", file_name, std::process::id(), n));
+        match std::fs::OpenOptions::new().write(true).create_new(true).open(&tmp_path) {
+            Ok(file) => return Ok((tmp_path, file)),
+            // Left behind by an earlier process that had the same id
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Helper function to find the file a write to `path` should replace: `path`
+// itself, or for a symlink the file at the end of the link chain, which
+// needn't exist yet
+fn symlink_destination(path: &Path) -> Result<PathBuf, std::io::Error> {
+    let mut current = path.to_path_buf();
+    // Bounded like the OS's own limit on following links, in case of a loop
+    for _ in 0..MAX_SYMLINK_HOPS {
+        match std::fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let target = std::fs::read_link(&current)?;
+                current = match current.parent() {
+                    Some(parent) => parent.join(target),
+                    None => target,
+                };
+            }
+            _ => return Ok(current),
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("Too many levels of symbolic links: {}", path.display()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Helper function to open a fresh temporary directory as the project
+    fn temp_project() -> (tempfile::TempDir, ProjectState) {
+        let dir = tempfile::tempdir().unwrap();
+        let project = ProjectState::default();
+        project.set_root(dir.path()).unwrap();
+        (dir, project)
+    }
+
+    #[test]
+    fn watch_started_with_relative_path_stops_with_it() {
+        let (dir, project) = temp_project();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+
+        let watches: Mutex<HashMap<PathBuf, ()>> = Mutex::default();
+        watches.lock().unwrap().insert(project.resolve_path("src").unwrap(), ());
+
+        assert!(remove_watch(&watches, &project, "src", None).unwrap().is_some());
+        assert!(watches.lock().unwrap().is_empty());
+        assert!(remove_watch(&watches, &project, "src", None).unwrap().is_none());
+    }
+
+    #[test]
+    fn workspace_roots_outside_the_project_are_accepted() {
+        let (_dir, project) = temp_project();
+        let other = tempfile::tempdir().unwrap();
+        let outside = other.path().join("lib.rs");
+
+        assert!(matches!(project.resolve_path(&outside.to_string_lossy()), Err(AppError::PermissionDenied(_))));
+        project.set_workspace_roots(std::iter::once(other.path())).unwrap();
+        assert!(project.resolve_path(&outside.to_string_lossy()).is_ok());
+    }
+
+    #[test]
+    fn picked_files_are_allowed_without_a_project() {
+        let dir = tempfile::tempdir().unwrap();
+        let picked = dir.path().join("notes.txt");
+        std::fs::write(&picked, "hello").unwrap();
+        let project = ProjectState::default();
+
+        assert!(matches!(project.resolve_path(&picked.to_string_lossy()), Err(AppError::PermissionDenied(_))));
+        project.allow_picked(&picked, false).unwrap();
+        assert!(project.resolve_path(&picked.to_string_lossy()).is_ok());
+        // Only the file itself, not its neighbours
+        let sibling = dir.path().join("other.txt");
+        assert!(matches!(project.resolve_path(&sibling.to_string_lossy()), Err(AppError::PermissionDenied(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_atomic_writes_through_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target.txt");
+        let link = dir.path().join("link.txt");
+        std::fs::write(&target, "old").unwrap();
+        std::os::unix::fs::symlink("target.txt", &link).unwrap();
+
+        write_atomic(&link, b"new").unwrap();
+
+        assert!(std::fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "new");
+    }
+
+    #[test]
+    fn concurrent_write_atomic_to_one_path_never_interleaves() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        let contents: Vec<String> = (0..16).map(|i| i.to_string().repeat(64 * 1024)).collect();
+
+        contents.par_iter().for_each(|content| write_atomic(&path, content.as_bytes()).unwrap());
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains(&written));
+        // No temp files are left behind
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn build_file_tree_stops_at_a_symlink_to_an_ancestor() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::os::unix::fs::symlink("..", dir.path().join("sub").join("up")).unwrap();
+        std::os::unix::fs::symlink(".", dir.path().join("self")).unwrap();
+
+        let scan = TreeScan { root: dir.path().to_path_buf(), ..TreeScan::default() };
+        let tree = build_file_tree(dir.path().to_path_buf(), &IgnoreRules::default(), &HashSet::new(), None, &scan)
+            .unwrap();
+
+        // Both links are listed, but neither is expanded
+        let children = tree.children.unwrap();
+        let link = children.iter().find(|child| child.name == "self").unwrap();
+        assert!(link.is_symlink && link.children.is_none());
+        let sub = children.iter().find(|child| child.name == "sub").unwrap();
+        let up = &sub.children.as_ref().unwrap()[0];
+        assert!(up.is_symlink && up.children.is_none());
+    }
+
+    #[test]
+    fn sort_entries_puts_folders_first_then_sorts_names_case_insensitively() {
+        let entry = |name: &str, is_directory: bool| FileEntry {
+            name: name.to_string(),
+            is_directory,
+            ..FileEntry::default()
+        };
+        let mut entries = vec![
+            entry("readme.md", false),
+            entry("src", false),
+            entry("Zeta", true),
+            entry("b.txt", false),
+            entry("src", true),
+            entry("README.md", false),
+            entry("alpha", true),
+            entry("A.txt", false),
+        ];
+
+        sort_entries(&mut entries);
+
+        let order: Vec<(&str, bool)> = entries.iter().map(|e| (e.name.as_str(), e.is_directory)).collect();
+        assert_eq!(order, vec![
+            ("alpha", true),
+            ("src", true),
+            ("Zeta", true),
+            ("A.txt", false),
+            ("b.txt", false),
+            ("README.md", false),
+            ("readme.md", false),
+            ("src", false),
+        ]);
+    }
+
+    #[test]
+    fn copy_recursive_copies_a_nested_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("from");
+        std::fs::create_dir_all(from.join("a").join("b")).unwrap();
+        std::fs::create_dir(from.join("empty")).unwrap();
+        std::fs::write(from.join("top.txt"), "top").unwrap();
+        std::fs::write(from.join("a").join("b").join("deep.txt"), "deep").unwrap();
+
+        let to = dir.path().join("to");
+        copy_recursive(&from, &to).unwrap();
+
+        assert_eq!(std::fs::read_to_string(to.join("top.txt")).unwrap(), "top");
+        assert_eq!(std::fs::read_to_string(to.join("a").join("b").join("deep.txt")).unwrap(), "deep");
+        assert!(to.join("empty").is_dir());
+        // The source is untouched
+        assert_eq!(std::fs::read_to_string(from.join("top.txt")).unwrap(), "top");
+    }
+
+    #[test]
+    fn trim_line_ends_strips_spaces_and_tabs_but_keeps_line_breaks() {
+        assert_eq!(trim_line_ends("a  \nb\t\t\nc \t \n"), "a\nb\nc\n");
+        assert_eq!(trim_line_ends("a \r\nb\t\r\n"), "a\r\nb\r\n");
+        assert_eq!(trim_line_ends("no newline  "), "no newline");
+        assert_eq!(trim_line_ends("  indented\n\n"), "  indented\n\n");
+        assert_eq!(trim_line_ends(""), "");
+    }
+
+    #[test]
+    fn existing_files_are_only_replaced_with_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("existing.txt");
+        std::fs::write(&existing, "saved").unwrap();
+        let new = dir.path().join("new.txt");
+
+        assert!(matches!(check_write_target(&existing, false, None), Err(AppError::AlreadyExists(_))));
+        assert!(check_write_target(&existing, true, None).is_ok());
+        // A file that doesn't exist yet can be written either way
+        assert!(check_write_target(&new, false, None).is_ok());
+        assert!(check_write_target(&new, true, None).is_ok());
+    }
+
+    #[test]
+    fn expected_hash_takes_the_place_of_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, "saved").unwrap();
+        let hash = format!("{:x}", Sha256::digest(b"saved"));
+
+        assert!(check_write_target(&path, false, Some(&hash)).is_ok());
+        assert!(matches!(check_write_target(&path, true, Some("0000")), Err(AppError::Conflict(_))));
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(check_write_target(&path, false, Some(&hash)), Err(AppError::Conflict(_))));
+    }
+
+    // Compares the parallel walk with the same walk confined to one thread,
+    // which is how build_file_tree ran before it used rayon. Creating the
+    // 50k files takes a while, so run it on demand with
+    // `cargo test --release -- --ignored --nocapture build_file_tree_benchmark`.
+    #[test]
+    #[ignore]
+    fn build_file_tree_benchmark() {
+        let dir = tempfile::tempdir().unwrap();
+        for d in 0..500 {
+            let sub = dir.path().join(format!("dir{}", d / 50)).join(format!("sub{}", d));
+            std::fs::create_dir_all(&sub).unwrap();
+            for f in 0..100 {
+                std::fs::write(sub.join(format!("file{}.rs", f)), "").unwrap();
+            }
+        }
+
+        let build = || {
+            let scan = TreeScan { root: dir.path().to_path_buf(), ..TreeScan::default() };
+            build_file_tree(dir.path().to_path_buf(), &IgnoreRules::default(), &HashSet::new(), None, &scan).unwrap()
+        };
+        let sequential_pool = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+
+        let started = Instant::now();
+        let sequential = sequential_pool.install(build);
+        let sequential_time = started.elapsed();
+        let started = Instant::now();
+        let parallel = build();
+        let parallel_time = started.elapsed();
+
+        println!("50k files: sequential {:?}, parallel {:?}", sequential_time, parallel_time);
+        // Same tree either way, in the same order
+        assert_eq!(serde_json::to_string(&sequential).unwrap(), serde_json::to_string(&parallel).unwrap());
+    }
+
+    #[test]
+    fn relative_path_without_a_root_keeps_the_path() {
+        let path = std::env::temp_dir().join("a").join("b");
+        assert_eq!(relative_path(&std::env::temp_dir(), &path), "a/b");
+        assert_eq!(relative_path(Path::new(""), &path), path.to_string_lossy());
+        assert_eq!(relative_path(&std::env::temp_dir().join("c"), &path), path.to_string_lossy());
+    }
+
+    #[test]
+    fn directory_pages_follow_the_full_listing() {
+        let (dir, project) = temp_project();
+        let root = project.root().unwrap();
+        for name in ["b.txt", "C.txt", "a.txt", "src", "Docs"] {
+            if name.contains('.') {
+                std::fs::write(dir.path().join(name), "").unwrap();
+            } else {
+                std::fs::create_dir(dir.path().join(name)).unwrap();
+            }
+        }
+
+        let names = |entries: Vec<FileEntry>| entries.into_iter().map(|entry| entry.name).collect::<Vec<_>>();
+        let all = names(list_children(&root, &root, &IgnoreRules::default()).unwrap());
+        assert_eq!(all, vec!["Docs", "src", "a.txt", "b.txt", "C.txt"]);
+
+        let (page, total) = list_children_page(&root, &root, &IgnoreRules::default(), 1, 3).unwrap();
+        assert_eq!(total, 5);
+        assert_eq!(names(page), all[1..4]);
+        let (page, total) = list_children_page(&root, &root, &IgnoreRules::default(), 9, 3).unwrap();
+        assert_eq!((page.len(), total), (0, 5));
+    }
+
+    // macOS filesystems refuse names that aren't valid UTF-8
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn path_id_reaches_files_with_non_utf8_names() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let (dir, project) = temp_project();
+        let name = std::ffi::OsStr::from_bytes(b"caf\xe9.txt");
+        let file = dir.path().join(name);
+        std::fs::write(&file, "bonjour").unwrap();
+
+        let listed = list_children(&project.root().unwrap(), &project.root().unwrap(), &IgnoreRules::default()).unwrap();
+        let entry = listed.iter().find(|entry| entry.name.starts_with("caf")).unwrap();
+        // The lossy path doesn't lead back to the file, the id does
+        assert!(!Path::new(&entry.path).exists());
+        let resolved = command_path(&project, &entry.path, Some(&entry.path_id)).unwrap();
+        assert_eq!(std::fs::read_to_string(&resolved).unwrap(), "bonjour");
+        assert_eq!(resolved.file_name(), Some(name));
+    }
+
+    #[test]
+    fn refresh_subtree_filters_like_the_full_walk() {
+        let (dir, project) = temp_project();
+        let root = project.root().unwrap();
+        std::fs::create_dir_all(dir.path().join("src").join("gen")).unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "*.log\n").unwrap();
+        std::fs::write(dir.path().join("src").join(".gitignore"), "gen/\n").unwrap();
+        std::fs::write(dir.path().join("src").join("main.rs"), "").unwrap();
+        std::fs::write(dir.path().join("src").join("debug.log"), "").unwrap();
+        std::fs::write(dir.path().join("src").join(".env"), "").unwrap();
+
+        let ignore = IgnoreRules::default().with_hidden(true);
+        let scan = TreeScan { root: root.clone(), ..TreeScan::default() };
+        let full = build_file_tree(root.clone(), &ignore, &HashSet::new(), None, &scan).unwrap();
+        let full_src = full.children.unwrap().into_iter().find(|child| child.name == "src").unwrap();
+
+        let (ignore, visited) = descend_to(&root, &root.join("src"), IgnoreRules::default().with_hidden(true)).unwrap();
+        let scan = TreeScan { root: root.clone(), ..TreeScan::default() };
+        let refreshed = build_file_tree(root.join("src"), &ignore, &visited, None, &scan).unwrap();
+
+        assert_eq!(serde_json::to_string(&full_src).unwrap(), serde_json::to_string(&refreshed).unwrap());
+        let names: Vec<String> = refreshed.children.unwrap().into_iter().map(|child| child.name).collect();
+        assert_eq!(names, vec![".env", ".gitignore", "main.rs"]);
+    }
+
+    #[test]
+    fn subfolder_listing_applies_project_root_ignore_files() {
+        let (dir, project) = temp_project();
+        let root = project.root().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join(".deepcodeignore"), "*.log\n").unwrap();
+        std::fs::write(dir.path().join("src").join("main.rs"), "").unwrap();
+        std::fs::write(dir.path().join("src").join("debug.log"), "").unwrap();
+
+        let ignore = IgnoreRules::for_ancestors(&root.join("src"), Some(&root));
+        assert!(ignore.is_ignored(&root.join("src").join("debug.log"), false));
+        assert!(!ignore.is_ignored(&root.join("src").join("main.rs"), false));
+
+        let mut walked = Vec::new();
+        walk_tree(&root.join("src"), Some(&root), &mut |path, _| {
+            walked.push(relative_path(&root, path));
+            true
+        })
+        .unwrap();
+        assert_eq!(walked, vec!["src/main.rs"]);
+    }
+
+    #[test]
+    fn utf8_window_keeps_whole_characters() {
+        let text = "aé€😀b".as_bytes();
+
+        // Cut inside "€": only "aé" fits
+        assert_eq!(utf8_window(text, 4, false), (0, 3));
+        // Starting inside "é": its continuation byte is skipped
+        assert_eq!(utf8_window(&text[2..], 4, false), (1, 3));
+        // Shorter than the first character: that character still comes back
+        assert_eq!(utf8_window(&text[6..], 1, false), (0, 4));
+        assert_eq!(utf8_window(&text[6..], 0, false), (0, 4));
+        // At the end of the file the rest is returned as is
+        assert_eq!(utf8_window(&text[10..], 8, true), (0, 1));
+        assert_eq!(utf8_window(b"", 8, true), (0, 0));
+    }
+
+    #[test]
+    fn reformat_json_keeps_numbers_and_key_order() {
+        let text = r#"{ "z": 1e2, "a": 1.10, "big": 123456789012345678901234567890, "n": -0 }"#;
+
+        assert_eq!(
+            reformat_json(text, false).unwrap(),
+            r#"{"z":1e2,"a":1.10,"big":123456789012345678901234567890,"n":-0}"#
+        );
+        assert_eq!(reformat_json("[1,2]", true).unwrap(), "[\n  1,\n  2\n]\n");
+        assert_eq!(
+            reformat_json("{\"a\": [ ], \"b\": {\"c\": \"x, \\\"y\\\": {z}\"}}", true).unwrap(),
+            "{\n  \"a\": [],\n  \"b\": {\n    \"c\": \"x, \\\"y\\\": {z}\"\n  }\n}\n"
+        );
+        assert!(matches!(reformat_json("{,}", true), Err(AppError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn evict_thumbnails_drops_the_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        for (i, name) in ["old.png", "mid.png", "new.png"].iter().enumerate() {
+            let path = dir.path().join(name);
+            std::fs::write(&path, [0u8; 100]).unwrap();
+            filetime::set_file_mtime(&path, FileTime::from_unix_time(1_000_000 + i as i64, 0)).unwrap();
+        }
+
+        evict_thumbnails(dir.path(), 250).unwrap();
+
+        assert!(!dir.path().join("old.png").exists());
+        assert!(dir.path().join("mid.png").exists());
+        assert!(dir.path().join("new.png").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dangling_symlinks_out_of_the_project_are_rejected() {
+        let (dir, project) = temp_project();
+        let outside = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path().join("new.txt"), dir.path().join("link.txt")).unwrap();
+        std::os::unix::fs::symlink(outside.path().join("newdir"), dir.path().join("linkdir")).unwrap();
+        std::os::unix::fs::symlink("inside.txt", dir.path().join("inward.txt")).unwrap();
+
+        assert!(matches!(project.resolve_path("link.txt"), Err(AppError::PermissionDenied(_))));
+        assert!(matches!(project.resolve_path("linkdir/file.txt"), Err(AppError::PermissionDenied(_))));
+        assert_eq!(
+            canonicalize_partial(&dir.path().join("link.txt")).unwrap(),
+            std::fs::canonicalize(outside.path()).unwrap().join("new.txt")
+        );
+        // A dangling link that stays inside the project is fine
+        assert!(project.resolve_path("inward.txt").is_ok());
+    }
+
+    #[test]
+    fn resolve_path_keeps_dollar_and_percent_names_literal() {
+        let (_dir, project) = temp_project();
+        let root = project.root().unwrap();
+
+        assert_eq!(project.resolve_path("Foo$Bar.java").unwrap(), root.join("Foo$Bar.java"));
+        assert_eq!(project.resolve_path("${NOT_SET_ANYWHERE}").unwrap(), root.join("${NOT_SET_ANYWHERE}"));
+        assert_eq!(project.resolve_path("%TEMP%").unwrap(), root.join("%TEMP%"));
+    }
+
+    #[test]
+    fn resolve_typed_path_expands_variables() {
+        let (_dir, project) = temp_project();
+        let root = project.root().unwrap();
+        std::env::set_var("DEEPCODE_TEST_DIR", "sub");
+
+        let typed = if cfg!(windows) { "%DEEPCODE_TEST_DIR%" } else { "$DEEPCODE_TEST_DIR" };
+        assert_eq!(project.resolve_typed_path(typed).unwrap(), root.join("sub"));
+        let unknown = if cfg!(windows) { "%DEEPCODE_NOT_SET%" } else { "$DEEPCODE_NOT_SET" };
+        assert!(matches!(project.resolve_typed_path(unknown), Err(AppError::InvalidInput(_))));
+    }
 }